@@ -1,52 +1,135 @@
+use serde::{Deserialize, Serialize};
 use std::collections::HashSet;
 
 pub use crate::prelude::*;
 
-#[derive(Clone, Copy, Debug, PartialEq)]
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
 pub struct Render{//渲染组件
     pub color : ColorPair,
     pub glyph : FontCharType,
 }
 
-#[derive(Clone, Copy, Debug, PartialEq)]
-pub struct Player;//玩家组件
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Player{//玩家组件
+    pub depth : u32,//当前所在的地下城层数，从1开始
+}
 
-#[derive(Clone, Copy, Debug, PartialEq)]
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
 pub struct Enemy;//敌人组件
 
-#[derive(Clone, Copy, Debug, PartialEq)]
-pub struct MovingRandomly;//随机移动组件
-
-#[derive(Clone, Copy, Debug, PartialEq)]
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
 pub struct WantsToMove{//移动实现组件
     pub entity : Entity,
     pub destination : Point
 }
 
-#[derive(Clone, Copy, Debug, PartialEq)]
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
 pub struct WantsToAttack{//攻击实现组件
     pub attacker : Entity,
     pub victim : Entity,
 }
 
-#[derive(Clone, Copy, Debug, PartialEq)]
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
 pub struct Health{//生命值组件
     pub current : i32,
     pub max : i32,
 }
 
-#[derive(Clone, PartialEq)]
+#[derive(Clone, PartialEq, Serialize, Deserialize)]
 pub struct Name(pub String);//名称组件
 
-#[derive(Clone, Copy, Debug, PartialEq)]
-pub struct ChasingPlayer;//追逐玩家组件
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Damage(pub DiceType);//攻击力组件，使用骰子记法
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Armor(pub i32);//护甲组件，抵消受到的伤害
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub enum EquipSlot {//装备栏位，同一栏位只能装备一件物品
+    Weapon,
+    Armor,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Equippable(pub EquipSlot);//可装备的物品
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Equipped;//已装备的物品
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub enum Behavior {//怪物可以选择的行为
+    Wander,//随机游走
+    Chase,//追击看到的玩家，或者赶往同伴呼叫的位置
+    Shoot { range : i32 },//与玩家保持距离，在射程内发射投射物
+    Flee { below : i32 },//生命值不超过below时逃离玩家
+    Guard { radius : i32 },//守在出生点，只追击进入范围内的玩家
+    CallAllies { radius : i32 },//看到玩家时呼叫范围内的同伴
+    UseItem,//受伤时去喝看得到的治疗药水
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Brain{//怪物的大脑，每回合给各个行为打分，执行得分最高的一个
+    pub behaviors : Vec<(Behavior, f32)>,//行为和它的权重
+    pub home : Point,//出生点
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Memory{//怪物记得的玩家位置，看到、听到或者被同伴呼叫时更新
+    pub pos : Point,
+    pub arrived : bool,//已经到过这里，正在附近搜索
+    pub search_turns : i32,//还要搜索的回合数，用完就忘掉
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Noise{//战斗发出的声音，范围内的怪物会过来查看
+    pub pos : Point,
+    pub radius : i32,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Projectile{//飞行中的投射物，命中后由战斗系统结算伤害
+    pub shooter : Entity,
+    pub path : Vec<Point>,//剩余的飞行路线
+}
 
-#[derive(Clone, Copy, Debug, PartialEq)]
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
 pub struct  Item;//物品组件
-#[derive(Clone, Copy, Debug, PartialEq)]
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
 pub struct  AmuletOfYala;//雅拉的护身符
 
-#[derive(Clone, Debug, PartialEq)]
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Carried(pub Entity);//被某个实体携带的物品
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct WantsToPickUp{//拾取物品组件
+    pub collector : Entity,
+    pub item : Entity,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct ProvidesHealing{//使用后恢复生命值
+    pub amount : i32,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct ProvidesDungeonMap;//使用后揭示整张地图
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct ProvidesTeleport;//使用后随机传送到地图上的某处
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct ProvidesAreaDamage{//使用后对周围的所有实体造成伤害
+    pub damage : i32,
+    pub radius : i32,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct WantsToUseItem{//使用物品组件
+    pub used_by : Entity,
+    pub item : Entity,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
 pub struct  FeildOfView{//视野组件
     pub visible_tiles : HashSet<Point>,
     pub radius : i32,
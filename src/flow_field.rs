@@ -0,0 +1,152 @@
+use crate::prelude::*;
+
+/** 逃跑地图：到玩家的距离乘以这个负数后重新松弛，逃跑者会绕开死角，而不是一头扎进角落 */
+const FLEE_FACTOR: f32 = -1.2;
+
+/** 缓存的Dijkstra图，起点和地图的版本号都没有变化时直接复用，不必重建整张图 */
+#[derive(Default)]
+pub struct FlowField {
+    starts: Vec<usize>,
+    revision: u64,
+    dijkstra: Option<DijkstraMap>,
+    builds: u32,
+    reuses: u32,
+}
+
+impl FlowField {
+    /** 从`starts`出发到每个地块的距离 */
+    pub fn get(&mut self, map: &Map, starts: &[usize]) -> &DijkstraMap {
+        self.get_or_build(map, starts, || {
+            DijkstraMap::new(map.width, map.height, starts, map, map.max_path_depth())
+        })
+    }
+
+    fn get_or_build(
+        &mut self,
+        map: &Map,
+        starts: &[usize],
+        build: impl FnOnce() -> DijkstraMap,
+    ) -> &DijkstraMap {
+        if let Some(dijkstra) = self.dijkstra.take()
+            && self.starts == starts
+            && self.revision == map.revision()
+        {
+            self.reuses += 1;
+            return self.dijkstra.insert(dijkstra);
+        }
+        self.builds += 1;
+        self.starts = starts.to_vec();
+        self.revision = map.revision();
+        self.dijkstra.insert(build())
+    }
+}
+
+/** 所有AI共用的几张Dijkstra图，只在玩家移动或者地图改变时重建 */
+#[derive(Default)]
+pub struct FlowFields {
+    toward_player: FlowField,
+    away_from_player: FlowField,
+    toward_exits: FlowField,
+}
+
+impl FlowFields {
+    /** 到玩家的距离，沿着它下坡走就是追击 */
+    pub fn toward_player(&mut self, map: &Map, player: Point) -> &DijkstraMap {
+        self.toward_player.get(map, &[map.point2d_to_index(player)])
+    }
+
+    /** 把到玩家的距离取反后重新松弛，沿着它下坡走就是逃跑 */
+    pub fn away_from_player(&mut self, map: &Map, player: Point) -> &DijkstraMap {
+        let starts = [map.point2d_to_index(player)];
+        let toward_player = &mut self.toward_player;
+        self.away_from_player.get_or_build(map, &starts, || {
+            let relaxed: Vec<(usize, f32)> = toward_player
+                .get(map, &starts)
+                .map
+                .iter()
+                .enumerate()
+                .filter(|(_, distance)| **distance < f32::MAX)
+                .map(|(idx, distance)| (idx, distance * FLEE_FACTOR))
+                .collect();
+            let mut flee_map = DijkstraMap::new_empty(map.width, map.height, map.max_path_depth());
+            for (idx, value) in &relaxed {
+                flee_map.map[*idx] = *value;
+            }
+            DijkstraMap::build_weighted(&mut flee_map, &relaxed, map);
+            flee_map
+        })
+    }
+
+    /** 到最近的出口的距离 */
+    pub fn toward_exits(&mut self, map: &Map) -> &DijkstraMap {
+        let exits: Vec<usize> = map
+            .tiles
+            .iter()
+            .enumerate()
+            .filter(|(_, t)| **t == TileType::Exit)
+            .map(|(idx, _)| idx)
+            .collect();
+        self.toward_exits.get(map, &exits)
+    }
+
+    /** 重建和复用的总次数，用于基准测试 */
+    pub fn stats(&self) -> (u32, u32) {
+        [&self.toward_player, &self.away_from_player, &self.toward_exits]
+            .iter()
+            .fold((0, 0), |(builds, reuses), field| {
+                (builds + field.builds, reuses + field.reuses)
+            })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reused_until_player_moves_or_map_changes() {
+        let mut map = Map::new(12, 12);
+        let mut fields = FlowFields::default();
+        let player = Point::new(2, 2);
+        fields.toward_player(&map, player);
+        fields.toward_player(&map, player);
+        assert_eq!(fields.stats(), (1, 1), "nothing changed");
+
+        fields.toward_player(&map, Point::new(3, 2));
+        assert_eq!(fields.stats(), (2, 1), "player moved");
+
+        //写入相同的地块不算地形变化
+        let idx = map.idx(6, 6);
+        map.set_tile(idx, TileType::Floor);
+        fields.toward_player(&map, Point::new(3, 2));
+        assert_eq!(fields.stats(), (2, 2), "same tile written");
+
+        map.set_tile(idx, TileType::Wall);
+        let distances = fields.toward_player(&map, Point::new(3, 2));
+        assert!(distances.map[idx] >= f32::MAX, "wall is not reachable");
+        assert_eq!(fields.stats(), (3, 2), "tile changed");
+
+        //换了一张同样大小的新地图也要重建
+        let map = Map::new(12, 12);
+        fields.toward_player(&map, Point::new(3, 2));
+        assert_eq!(fields.stats(), (4, 2), "new map");
+    }
+
+    #[test]
+    fn flee_map_follows_chase_map() {
+        let mut map = Map::new(12, 12);
+        let mut fields = FlowFields::default();
+        let player = Point::new(2, 2);
+        fields.away_from_player(&map, player);
+        fields.away_from_player(&map, player);
+        //逃跑地图重建时也建了一次追击地图
+        assert_eq!(fields.stats(), (2, 1));
+
+        fields.toward_player(&map, player);
+        assert_eq!(fields.stats(), (2, 2), "chase map built for the flee map is reused");
+
+        map.tiles_changed();
+        fields.away_from_player(&map, player);
+        assert_eq!(fields.stats(), (4, 2), "both maps rebuilt after the map changed");
+    }
+}
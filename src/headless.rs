@@ -0,0 +1,311 @@
+use crate::{LevelOptions, State};
+use crate::prelude::*;
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+
+/** 未指定`--max-turns`时每局的回合上限，避免卡住的局面无限运行 */
+const DEFAULT_MAX_TURNS: u32 = 2000;
+
+#[derive(Clone, Copy, PartialEq)]
+enum Outcome {
+    Victory,
+    GameOver,
+    Timeout,
+}
+
+/// 无窗口模式的选项
+#[derive(Default)]
+pub struct HeadlessOptions {
+    pub max_turns: Option<u32>, //每局的回合上限，默认为`DEFAULT_MAX_TURNS`
+    pub script: Option<String>, //按键脚本文件，脚本用完后交给自动玩家
+    pub bench: bool,            //统计怪物回合的耗时和Dijkstra图的复用情况
+}
+
+struct GameReport {
+    seed: u64,
+    outcome: Outcome,
+    depth: u32,
+    turns: u32,
+    monster_turns: u32,
+    monster_time: Duration, //执行怪物回合的系统花费的总时间
+    flow_builds: u32,
+    flow_reuses: u32,
+}
+
+/** 不打开窗口，连续模拟若干局并输出统计结果，用于平衡性统计和回归测试 */
+pub fn run(
+    templates: &Templates,
+    vaults: &Vaults,
+    seed: Option<u64>,
+    games: u32,
+    options: &HeadlessOptions,
+    level_options: &LevelOptions,
+) -> BError {
+    let script = match &options.script {
+        Some(path) => load_script(path)?,
+        None => Vec::new(),
+    };
+    let max_turns = options.max_turns.unwrap_or(DEFAULT_MAX_TURNS);
+    let mut reports = Vec::new();
+    for game in 0..games {
+        //指定种子时每局依次加一，整批模拟可以完整复现
+        let game_seed = seed.map(|s| s.wrapping_add(u64::from(game)));
+        let mut state = State::new(
+            templates.clone(),
+            vaults.clone(),
+            game_seed,
+            false,
+            level_options.clone(),
+        );
+        let report = play(&mut state, max_turns, script.iter().copied().collect());
+        println!(
+            "game {:>4}  seed {:>20}  {:<8}  depth {}  turns {}",
+            game + 1,
+            report.seed,
+            match report.outcome {
+                Outcome::Victory => "victory",
+                Outcome::GameOver => "death",
+                Outcome::Timeout => "timeout",
+            },
+            report.depth,
+            report.turns
+        );
+        if options.bench {
+            println!(
+                "           monster turn {:>8.1} us  flow fields built {}  reused {}",
+                turn_cost_us(report.monster_time, report.monster_turns),
+                report.flow_builds,
+                report.flow_reuses
+            );
+        }
+        reports.push(report);
+    }
+    print_summary(&reports, options.bench);
+    Ok(())
+}
+
+/** 先按脚本中的按键操作，脚本用完后交给自动玩家 */
+fn play(state: &mut State, max_turns: u32, mut script: VecDeque<VirtualKeyCode>) -> GameReport {
+    let mut turns = 0;
+    let mut monster_turns = 0;
+    let mut monster_time = Duration::ZERO;
+    let mut goal_field = FlowField::default();
+    let outcome = loop {
+        match state.turn_state() {
+            TurnState::Victory => break Outcome::Victory,
+            TurnState::GameOver => break Outcome::GameOver,
+            TurnState::AwaitingInput | TurnState::ShowingInventory => {
+                if turns >= max_turns {
+                    break Outcome::Timeout;
+                }
+                if state.turn_state() == TurnState::AwaitingInput {
+                    turns += 1;
+                }
+                let key = script
+                    .pop_front()
+                    .unwrap_or_else(|| auto_key(state, &mut goal_field));
+                state.resources.insert(Some(key));
+            }
+            TurnState::MonsterTurn => {
+                monster_turns += 1;
+                let start = Instant::now();
+                state.step();
+                monster_time += start.elapsed();
+                continue;
+            }
+            _ => {}
+        }
+        state.step();
+    };
+    let (flow_builds, flow_reuses) = state.resources.get::<FlowFields>().unwrap().stats();
+    let depth = <&Player>::query()
+        .iter(&state.ecs)
+        .next()
+        .map_or(0, |player| player.depth);
+    GameReport {
+        seed: state.seed,
+        outcome,
+        depth,
+        turns,
+        monster_turns,
+        monster_time,
+        flow_builds,
+        flow_reuses,
+    }
+}
+
+/** 平均每个怪物回合花费的微秒数 */
+fn turn_cost_us(time: Duration, turns: u32) -> f64 {
+    time.as_secs_f64() * 1e6 / f64::from(turns.max(1))
+}
+
+/** 简单的自动玩家：攻击相邻的敌人，捡起脚下的物品，否则沿最短路径走向楼梯或护身符 */
+fn auto_key(state: &State, goal_field: &mut FlowField) -> VirtualKeyCode {
+    if state.turn_state() == TurnState::ShowingInventory {
+        return VirtualKeyCode::Escape;
+    }
+    let ecs = &state.ecs;
+    let map = state.resources.get::<Map>().unwrap();
+    let player_pos = *<&Point>::query()
+        .filter(component::<Player>())
+        .iter(ecs)
+        .next()
+        .unwrap();
+
+    let adjacent_enemy = <&Point>::query()
+        .filter(component::<Enemy>())
+        .iter(ecs)
+        .map(|pos| *pos - player_pos)
+        .find(|delta| delta.x.abs() + delta.y.abs() == 1);
+    if let Some(delta) = adjacent_enemy {
+        return direction_key(delta);
+    }
+
+    let item_here = <&Point>::query()
+        .filter(component::<Item>() & !component::<AmuletOfYala>())
+        .iter(ecs)
+        .any(|pos| *pos == player_pos);
+    if item_here {
+        return VirtualKeyCode::G;
+    }
+
+    let amulet = <&Point>::query()
+        .filter(component::<AmuletOfYala>())
+        .iter(ecs)
+        .next()
+        .map(|pos| map.point2d_to_index(*pos));
+    //Dijkstra图不会把起点本身记为0，目标就在相邻格时直接走过去
+    let adjacent_goal = amulet
+        .into_iter()
+        .chain(
+            map.tiles
+                .iter()
+                .enumerate()
+                .filter(|(_, t)| **t == TileType::Exit)
+                .map(|(idx, _)| idx),
+        )
+        .map(|goal| map.index_to_point2d(goal) - player_pos)
+        .find(|delta| delta.x.abs() + delta.y.abs() == 1);
+    if let Some(delta) = adjacent_goal {
+        return direction_key(delta);
+    }
+    let mut flow_fields = state.resources.get_mut::<FlowFields>().unwrap();
+    let dijkstra_map = match amulet {
+        Some(amulet) => goal_field.get(&map, &[amulet]),
+        None => flow_fields.toward_exits(&map),
+    };
+    let player_idx = map.point2d_to_index(player_pos);
+    if let Some(next) = DijkstraMap::find_lowest_exit(dijkstra_map, player_idx, &*map) {
+        return direction_key(map.index_to_point2d(next) - player_pos);
+    }
+    //无路可走时原地等待
+    VirtualKeyCode::Space
+}
+
+fn direction_key(delta: Point) -> VirtualKeyCode {
+    match (delta.x.signum(), delta.y.signum()) {
+        (-1, _) => VirtualKeyCode::Left,
+        (1, _) => VirtualKeyCode::Right,
+        (_, -1) => VirtualKeyCode::Up,
+        (_, 1) => VirtualKeyCode::Down,
+        _ => VirtualKeyCode::Space,
+    }
+}
+
+/** 脚本文件由空白分隔的按键名组成，如`W W D G I a Esc` */
+fn load_script(path: &str) -> Result<Vec<VirtualKeyCode>, String> {
+    const LETTERS: [VirtualKeyCode; 26] = [
+        VirtualKeyCode::A,
+        VirtualKeyCode::B,
+        VirtualKeyCode::C,
+        VirtualKeyCode::D,
+        VirtualKeyCode::E,
+        VirtualKeyCode::F,
+        VirtualKeyCode::G,
+        VirtualKeyCode::H,
+        VirtualKeyCode::I,
+        VirtualKeyCode::J,
+        VirtualKeyCode::K,
+        VirtualKeyCode::L,
+        VirtualKeyCode::M,
+        VirtualKeyCode::N,
+        VirtualKeyCode::O,
+        VirtualKeyCode::P,
+        VirtualKeyCode::Q,
+        VirtualKeyCode::R,
+        VirtualKeyCode::S,
+        VirtualKeyCode::T,
+        VirtualKeyCode::U,
+        VirtualKeyCode::V,
+        VirtualKeyCode::W,
+        VirtualKeyCode::X,
+        VirtualKeyCode::Y,
+        VirtualKeyCode::Z,
+    ];
+    let text =
+        std::fs::read_to_string(path).map_err(|e| format!("cannot read script {path}: {e}"))?;
+    text.split_whitespace()
+        .map(|token| match token.to_ascii_lowercase().as_str() {
+            "up" => Ok(VirtualKeyCode::Up),
+            "down" => Ok(VirtualKeyCode::Down),
+            "left" => Ok(VirtualKeyCode::Left),
+            "right" => Ok(VirtualKeyCode::Right),
+            "esc" | "escape" => Ok(VirtualKeyCode::Escape),
+            "space" | "wait" => Ok(VirtualKeyCode::Space),
+            letter if letter.len() == 1 && letter.as_bytes()[0].is_ascii_lowercase() => {
+                Ok(LETTERS[usize::from(letter.as_bytes()[0] - b'a')])
+            }
+            _ => Err(format!("unknown key '{token}' in script {path}")),
+        })
+        .collect()
+}
+
+fn print_summary(reports: &[GameReport], bench: bool) {
+    let count = |outcome: Outcome| reports.iter().filter(|r| r.outcome == outcome).count();
+    let games = f64::from(u32::try_from(reports.len()).unwrap_or(u32::MAX).max(1));
+    let total_depth: f64 = reports.iter().map(|r| f64::from(r.depth)).sum();
+    let total_turns: f64 = reports.iter().map(|r| f64::from(r.turns)).sum();
+    println!("----");
+    println!(
+        "games {}  victories {}  deaths {}  timeouts {}",
+        reports.len(),
+        count(Outcome::Victory),
+        count(Outcome::GameOver),
+        count(Outcome::Timeout)
+    );
+    println!(
+        "average depth {:.2}  average turns {:.1}",
+        total_depth / games,
+        total_turns / games
+    );
+    if bench {
+        let monster_time: Duration = reports.iter().map(|r| r.monster_time).sum();
+        let monster_turns: u32 = reports.iter().map(|r| r.monster_turns).sum();
+        println!(
+            "monster turn {:.1} us on average  flow fields built {}  reused {}",
+            turn_cost_us(monster_time, monster_turns),
+            reports.iter().map(|r| r.flow_builds).sum::<u32>(),
+            reports.iter().map(|r| r.flow_reuses).sum::<u32>()
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /** `--visualize`在无窗口模式下被忽略，模拟照常结束 */
+    #[test]
+    fn visualize_does_not_stall_headless_games() {
+        let templates = Templates::load().expect("templates should load");
+        let vaults = Vaults::load().expect("vaults should load");
+        let level_options = LevelOptions {
+            visualize: true,
+            ..LevelOptions::default()
+        };
+        let mut state = State::new(templates, vaults, Some(1), false, level_options);
+        assert!(state.mapgen.is_none());
+        let report = play(&mut state, 50, VecDeque::new());
+        assert!(report.turns > 0, "the player never got a turn");
+    }
+}
@@ -1,13 +1,18 @@
 #![warn(clippy::pedantic)]
 
 mod camera;
+mod flow_field;
 mod map;
+mod headless;
 mod map_builder;
+mod save;
+mod spatial_index;
 //mod player;已经被弃用，将转为Legion的ECS模式
 mod components;
 mod spawner;
 mod system;
 mod turn_state;
+mod visualizer;
 
 mod prelude {
     pub use bracket_lib::prelude::*;
@@ -18,9 +23,13 @@ mod prelude {
     pub const SCREEN_HEIGHT: i32 = 50;
     pub const DISPLAY_WIDTH: i32 = SCREEN_WIDTH / 2;
     pub const DISPLAY_HEIGHT: i32 = SCREEN_HEIGHT / 2;
+    pub const DUNGEON_DEPTH: u32 = 3; //地下城的总层数，护身符在最深一层
     pub use crate::camera::*;
+    pub use crate::flow_field::*;
     pub use crate::map::*;
     pub use crate::map_builder::*;
+    pub use crate::save::*;
+    pub use crate::spatial_index::*;
     //pub use crate::player::*;
     pub use crate::components::*;
     pub use crate::spawner::*;
@@ -29,6 +38,9 @@ mod prelude {
 }
 
 use prelude::*;
+use std::collections::HashSet;
+use headless::HeadlessOptions;
+use visualizer::MapGenVisualizer;
 
 /*struct State {//原有的类/方法模式
     map: Map,
@@ -39,40 +51,130 @@ struct State {
     ecs: World,
     resources: Resources,
     input_systems: Schedule,
+    inventory_systems: Schedule,
     player_systems: Schedule,
     monster_systems: Schedule,
+    templates: Templates,
+    vaults: Vaults,
+    fixed_seed: Option<u64>, //命令行指定的种子，重新开始时沿用
+    seed: u64,               //本局使用的种子
+    level_options: LevelOptions,
+    mapgen: Option<MapGenVisualizer>,
+}
+
+/// 与地图生成相关的开发选项
+#[derive(Clone, Default)]
+struct LevelOptions {
+    visualize: bool,            //每层开始前回放地图生成过程
+    export_map: Option<String>, //每生成一层就把地图导出到这个文件
+    first_level: Option<String>, //代替第一层的文本地图，启动时已经检查过
+    map_size: Option<(i32, i32)>, //生成的地图尺寸，默认为`MAP_WIDTH`×`MAP_HEIGHT`
 }
 
 impl State {
-    fn new() -> Self {
-        let mut ecs = World::default();
-        let mut resources = Resources::default();
-        let mut rng = RandomNumberGenerator::new();
-        let map_builder = MapBuilder::new(&mut rng);
-        spawn_player(&mut ecs, map_builder.player_start);
-        spawn_amulet_of_yala(&mut ecs, map_builder.amulet_start);
-        /*map_builder
-        .rooms
-        .iter()
-        .skip(1)
-        .map(|r| r.center())
-        .for_each(|pos| {
-            spawn_enemy(&mut ecs, &mut rng, pos);
-        });*/
-        map_builder.monster_spawns.iter().for_each(|pos| {
-            spawn_enemy(&mut ecs, &mut rng, *pos);
-        });
-        resources.insert(map_builder.map);
-        resources.insert(Camera::new(map_builder.player_start));
-        resources.insert(TurnState::AwaitingInput);
-        Self {
-            ecs,
-            resources,
-            input_systems: build_input_schedule(),
-            player_systems: build_player_schedule(),
-            monster_systems: build_monster_schedule(),
+    /** render为false时只包含游戏逻辑的系统，供无窗口模式使用，这时也不回放地图生成过程 */
+    fn new(
+        templates: Templates,
+        vaults: Vaults,
+        fixed_seed: Option<u64>,
+        render: bool,
+        mut level_options: LevelOptions,
+    ) -> Self {
+        //无窗口模式下没有地方回放，停在ShowingMapGen会让模拟永远不结束
+        level_options.visualize &= render;
+        let mut state = Self {
+            ecs: World::default(),
+            resources: Resources::default(),
+            input_systems: build_input_schedule(render),
+            inventory_systems: build_inventory_schedule(render),
+            player_systems: build_player_schedule(render),
+            monster_systems: build_monster_schedule(render),
+            templates,
+            vaults,
+            fixed_seed,
+            seed: 0,
+            level_options,
+            mapgen: None,
+        };
+        state.reset_game_state();
+        state
+    }
+
+    fn turn_state(&self) -> TurnState {
+        *self.resources.get::<TurnState>().unwrap()
+    }
+
+    /** 根据当前的TurnState执行一次对应的系统，不涉及任何绘制窗口的操作 */
+    fn step(&mut self) {
+        match self.turn_state() {
+            TurnState::AwaitingInput => {
+                self.input_systems
+                    .execute(&mut self.ecs, &mut self.resources);
+            }
+            TurnState::ShowingInventory => {
+                self.inventory_systems
+                    .execute(&mut self.ecs, &mut self.resources);
+            }
+            TurnState::PlayerTurn => {
+                self.player_systems
+                    .execute(&mut self.ecs, &mut self.resources);
+            }
+            TurnState::MonsterTurn => {
+                self.monster_systems
+                    .execute(&mut self.ecs, &mut self.resources);
+            }
+            TurnState::NextLevel => {
+                self.advance_level();
+            }
+            TurnState::GameOver
+            | TurnState::Victory
+            | TurnState::MainMenu
+            | TurnState::ShowingMapGen => {}
+        }
+    }
+
+    fn main_menu(&mut self, ctx: &mut BTerm) {
+        ctx.set_active_console(2);
+        ctx.print_color_centered(2, YELLOW, BLACK, "A saved game was found.");
+        ctx.print_color_centered(4, GREEN, BLACK, "Press C to continue your journey.");
+        ctx.print_color_centered(5, WHITE, BLACK, "Press N to start a new game.");
+        match ctx.key {
+            Some(VirtualKeyCode::C) => match load_game() {
+                Ok(loaded) => {
+                    self.mapgen = None;
+                    self.seed = loaded.seed;
+                    self.ecs = loaded.ecs;
+                    self.resources = loaded.resources;
+                }
+                Err(e) => {
+                    eprintln!("{e}");
+                    self.resources.insert(TurnState::AwaitingInput);
+                }
+            },
+            Some(VirtualKeyCode::N) => {
+                delete_save();
+                self.resources.insert(TurnState::AwaitingInput);
+                self.start_visualizer();
+            }
+            _ => {}
         }
     }
+
+    /** 关闭窗口时保存正在进行的游戏，已经结束的游戏和还没开始的关卡不保存 */
+    fn save_and_quit(&mut self, ctx: &mut BTerm) {
+        match self.turn_state() {
+            TurnState::GameOver
+            | TurnState::Victory
+            | TurnState::MainMenu
+            | TurnState::ShowingMapGen => {}
+            _ => {
+                if let Err(e) = save_game(&self.ecs, &self.resources, self.seed) {
+                    eprintln!("{e}");
+                }
+            }
+        }
+        ctx.quit();
+    }
     fn game_over(&mut self, ctx: &mut BTerm) {
         ctx.set_active_console(2);
         ctx.print_color_centered(2, RED, BLACK, "Your quest has ended.");
@@ -95,6 +197,7 @@ impl State {
             "Don't worry, you can always try again with a new hero.",
         );
         ctx.print_color_centered(9, GREEN, BLACK, "Press 1 to play again.");
+        ctx.print_color_centered(11, GRAY, BLACK, format!("Seed: {}", self.seed));
 
         if let Some(VirtualKeyCode::Key1) = ctx.key {
             self.reset_game_state();
@@ -117,18 +220,23 @@ impl State {
             "Your town is saved, and you can return to your normal life.",
         );
         ctx.print_color_centered(7, GREEN, BLACK, "Press 1 to play again.");
+        ctx.print_color_centered(9, GRAY, BLACK, format!("Seed: {}", self.seed));
         if let Some(VirtualKeyCode::Key1) = ctx.key {
             self.reset_game_state();
         }
     }
 
+    /** 开始新的一局，所有随机数都来自同一个以seed初始化的随机数生成器，便于复现 */
     fn reset_game_state(&mut self) {
+        self.seed = self
+            .fixed_seed
+            .unwrap_or_else(|| RandomNumberGenerator::new().next_u64());
         self.ecs = World::default();
         self.resources = Resources::default();
-        let mut rng = RandomNumberGenerator::new();
-        let map_builder = MapBuilder::new(&mut rng);
+        self.resources.insert(FlowFields::default());
+        let mut rng = RandomNumberGenerator::seeded(self.seed);
+        let mut map_builder = self.build_map(&mut rng, 1);
         spawn_player(&mut self.ecs, map_builder.player_start);
-        spawn_amulet_of_yala(&mut self.ecs, map_builder.amulet_start);
         /*map_builder
         .rooms
         .iter()
@@ -137,17 +245,135 @@ impl State {
         .for_each(|pos| {
             spawn_enemy(&mut self.ecs, &mut rng, pos);
         });*/
-        map_builder.monster_spawns.iter().for_each(|pos| {
-            spawn_enemy(&mut self.ecs, &mut rng, *pos);
-        });
+        spawn_level(
+            &mut self.ecs,
+            &mut rng,
+            &self.templates,
+            &mut map_builder,
+            1,
+        );
+        self.resources
+            .insert(Camera::new(map_builder.player_start, &map_builder.map));
+        self.resources
+            .insert(SpatialIndex::build(&self.ecs, &map_builder.map));
         self.resources.insert(map_builder.map);
-        self.resources.insert(Camera::new(map_builder.player_start));
+        self.resources.insert(map_builder.theme);
         self.resources.insert(TurnState::AwaitingInput);
+        self.resources.insert(rng);
+        self.start_visualizer();
+    }
+
+    /** 进入下一层：保留玩家实体及其携带的物品，重建地图、怪物和摄像机 */
+    fn advance_level(&mut self) {
+        let player_entity = *<Entity>::query()
+            .filter(component::<Player>())
+            .iter(&self.ecs)
+            .next()
+            .unwrap();
+        let mut entities_to_keep = HashSet::new();
+        entities_to_keep.insert(player_entity);
+        <(Entity, &Carried)>::query()
+            .iter(&self.ecs)
+            .filter(|(_, carried)| carried.0 == player_entity)
+            .for_each(|(entity, _)| {
+                entities_to_keep.insert(*entity);
+            });
+        let to_remove: Vec<Entity> = <Entity>::query()
+            .iter(&self.ecs)
+            .filter(|entity| !entities_to_keep.contains(*entity))
+            .copied()
+            .collect();
+        for entity in to_remove {
+            self.ecs.remove(entity);
+        }
+
+        let mut rng = self
+            .resources
+            .remove::<RandomNumberGenerator>()
+            .expect("RNG resource missing");
+        let depth = <&Player>::query()
+            .iter(&self.ecs)
+            .next()
+            .map_or(1, |player| player.depth + 1);
+        let mut map_builder = self.build_map(&mut rng, depth);
+        <(&mut Player, &mut Point, &mut FeildOfView)>::query().for_each_mut(
+            &mut self.ecs,
+            |(player, pos, fov)| {
+                player.depth = depth;
+                *pos = map_builder.player_start;
+                fov.is_dirty = true;
+            },
+        );
+        spawn_level(
+            &mut self.ecs,
+            &mut rng,
+            &self.templates,
+            &mut map_builder,
+            depth,
+        );
+        self.resources
+            .insert(Camera::new(map_builder.player_start, &map_builder.map));
+        self.resources
+            .insert(SpatialIndex::build(&self.ecs, &map_builder.map));
+        self.resources.insert(map_builder.map);
+        self.resources.insert(map_builder.theme);
+        self.resources.insert(TurnState::AwaitingInput);
+        self.resources.insert(rng);
+        self.start_visualizer();
+    }
+
+    /** 生成一层地图，第一层可以用`--level`指定的文本地图代替，按需要准备回放并导出 */
+    fn build_map(&mut self, rng: &mut RandomNumberGenerator, depth: u32) -> MapBuilder {
+        let map_builder = if let (Some(text), 1) = (&self.level_options.first_level, depth) {
+            let mut map_builder = MapBuilder::from_ascii(text).expect("启动时已经检查过文本地图");
+            if depth < DUNGEON_DEPTH {
+                map_builder.place_exit();
+            }
+            map_builder
+        } else {
+            let (width, height) = self.level_options.map_size.unwrap_or((MAP_WIDTH, MAP_HEIGHT));
+            let visualize = self.level_options.visualize;
+            MapBuilder::new(rng, &self.vaults, width, height, depth, visualize)
+        };
+        if self.level_options.visualize {
+            self.mapgen = Some(MapGenVisualizer::new(&map_builder, self.seed));
+        }
+        if let Some(path) = &self.level_options.export_map
+            && let Err(e) = std::fs::write(path, map_builder.to_ascii())
+        {
+            eprintln!("无法导出地图到{path}: {e}");
+        }
+        map_builder
+    }
+
+    /** 有待回放的地图生成过程时，先进入回放状态 */
+    fn start_visualizer(&mut self) {
+        if self.mapgen.is_some() {
+            self.resources.insert(TurnState::ShowingMapGen);
+        }
+    }
+
+    fn show_mapgen(&mut self, ctx: &mut BTerm) {
+        let finished = self.mapgen.as_mut().is_none_or(|mapgen| mapgen.tick(ctx));
+        if finished {
+            self.mapgen = None;
+            self.resources.insert(TurnState::AwaitingInput);
+        }
     }
 }
 
 impl GameState for State {
     fn tick(&mut self, ctx: &mut BTerm) {
+        let mut close_requested = false;
+        INPUT.lock().for_each_message(|event| {
+            if event == BEvent::CloseRequested {
+                close_requested = true;
+            }
+        });
+        if close_requested {
+            self.save_and_quit(ctx);
+            return;
+        }
         ctx.set_active_console(0);
         ctx.cls();
         ctx.set_active_console(1);
@@ -158,34 +384,122 @@ impl GameState for State {
         ctx.set_active_console(0);
         self.resources.insert(Point::from_tuple(ctx.mouse_pos()));
         //根据当前的TurnState，执行不同的系统
-        let current_state = self.resources.get::<TurnState>().unwrap().clone();
-        match current_state {
-            TurnState::AwaitingInput => {
-                self.input_systems
-                    .execute(&mut self.ecs, &mut self.resources);
-            }
-            TurnState::PlayerTurn => {
-                self.player_systems
-                    .execute(&mut self.ecs, &mut self.resources);
-            }
-            TurnState::MonsterTurn => {
-                self.monster_systems
-                    .execute(&mut self.ecs, &mut self.resources);
-            }
+        match self.turn_state() {
             TurnState::GameOver => {
                 self.game_over(ctx);
             }
             TurnState::Victory => {
                 self.victory(ctx);
             }
+            TurnState::MainMenu => {
+                self.main_menu(ctx);
+            }
+            TurnState::ShowingMapGen => {
+                self.show_mapgen(ctx);
+            }
+            _ => {
+                self.step();
+                //游戏刚结束时删除存档，已经结束的游戏不能再继续
+                if matches!(self.turn_state(), TurnState::GameOver | TurnState::Victory) {
+                    delete_save();
+                }
+            }
         }
         render_draw_buffer(ctx).expect("Render Error");
     }
 }
 
+/// 命令行参数
+#[derive(Default)]
+struct CliOptions {
+    seed: Option<u64>,             //`--seed <数字>`
+    headless: Option<u32>,         //`--headless <局数>`，不打开窗口连续模拟多局
+    max_turns: Option<u32>,        //`--max-turns <回合数>`，无窗口模式下每局的回合上限
+    script: Option<String>,        //`--script <文件>`，无窗口模式下按文件中的按键操作
+    visualize: bool,               //`--visualize`，每层开始前回放地图生成过程
+    export_map: Option<String>,    //`--export-map <文件>`，把每一层生成的地图导出为文本
+    level: Option<String>,         //`--level <文件>`，用文本地图代替第一层
+    map_size: Option<(i32, i32)>,  //`--map-size <宽>x<高>`，生成更大或更小的地图
+    bench: bool,                   //`--bench`，无窗口模式下统计怪物回合的耗时
+}
+
+/// 解析命令行参数，支持`--name value`和`--name=value`两种写法
+fn parse_args() -> Result<CliOptions, String> {
+    fn number<T: std::str::FromStr>(name: &str, value: Option<String>) -> Result<T, String> {
+        value
+            .and_then(|v| v.parse::<T>().ok())
+            .ok_or_else(|| format!("{name} requires a non-negative integer"))
+    }
+    let mut options = CliOptions::default();
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        let (name, inline_value) = match arg.split_once('=') {
+            Some((name, value)) => (name.to_string(), Some(value.to_string())),
+            None => (arg.clone(), None),
+        };
+        let mut value = || inline_value.clone().or_else(|| args.next());
+        match name.as_str() {
+            "--seed" => options.seed = Some(number(&name, value())?),
+            "--headless" => options.headless = Some(number(&name, value())?),
+            "--max-turns" => options.max_turns = Some(number(&name, value())?),
+            "--visualize" => options.visualize = true,
+            "--bench" => options.bench = true,
+            "--map-size" => {
+                let size = value().unwrap_or_default();
+                options.map_size = Some(
+                    size.split_once('x')
+                        .and_then(|(w, h)| Some((w.parse().ok()?, h.parse().ok()?)))
+                        .ok_or_else(|| format!("{name} requires a size like 160x100"))?,
+                );
+            }
+            "--export-map" => {
+                options.export_map =
+                    Some(value().ok_or_else(|| format!("{name} requires a file"))?);
+            }
+            "--level" => {
+                options.level = Some(value().ok_or_else(|| format!("{name} requires a file"))?);
+            }
+            "--script" => {
+                options.script = Some(value().ok_or_else(|| format!("{name} requires a file"))?);
+            }
+            _ => return Err(format!("unknown argument: {arg}")),
+        }
+    }
+    Ok(options)
+}
+
 /// 游戏的主函数，负责初始化游戏环境并启动主循环
 /// 返回BError类型，用于处理可能的初始化错误
 fn main() -> BError {
+    // 启动时读取实体模板和地窖，有误时直接报错退出
+    let templates = Templates::load()?;
+    let vaults = Vaults::load()?;
+    let options = parse_args()?;
+    let first_level = options.level.as_deref().map(std::fs::read_to_string).transpose()?;
+    if let Some(text) = &first_level {
+        MapBuilder::from_ascii(text)?;
+    }
+    let level_options = LevelOptions {
+        visualize: options.visualize,
+        export_map: options.export_map.clone(),
+        first_level,
+        map_size: options.map_size,
+    };
+    if let Some(games) = options.headless {
+        let headless_options = HeadlessOptions {
+            max_turns: options.max_turns,
+            script: options.script,
+            bench: options.bench,
+        };
+        return headless::run(
+            &templates,
+            &vaults,
+            options.seed,
+            games,
+            &headless_options,
+            &level_options,
+        );
+    }
     // 创建BTermBuilder实例，用于配置游戏窗口和渲染环境
     let context = BTermBuilder::new()
         // 设置游戏窗口标题为"小张的地下城冒险"
@@ -211,7 +525,15 @@ fn main() -> BError {
         // 构建BTerm上下文，?操作符用于错误传播
         .build()?;
 
+    // 开启事件队列，这样关闭窗口时可以先存档再退出
+    INPUT.lock().activate_event_queue();
+
     // 启动游戏主循环，传入构建好的上下文和初始游戏状态
     // main_loop会持续运行，直到游戏结束或发生错误
-    main_loop(context, State::new())
+    let mut state = State::new(templates, vaults, options.seed, true, level_options);
+    //有存档时先显示菜单，让玩家选择继续还是开始新游戏
+    if has_save() {
+        state.resources.insert(TurnState::MainMenu);
+    }
+    main_loop(context, state)
 }
@@ -1,52 +1,140 @@
 use crate::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::sync::atomic::{AtomicU64, Ordering};
 
-const NUM_TILES: usize = (SCREEN_WIDTH * SCREEN_HEIGHT) as usize;
+/** 默认的地图尺寸，地图本身记录自己的宽高，可以比屏幕大也可以比屏幕小 */
+pub const MAP_WIDTH: i32 = SCREEN_WIDTH;
+pub const MAP_HEIGHT: i32 = SCREEN_HEIGHT;
 
-#[derive(Copy, Clone, PartialEq)]
+#[derive(Copy, Clone, Debug, PartialEq, Serialize, Deserialize)]
 pub enum TileType {
     Wall,
     Floor,
+    Exit,       //通往下一层的楼梯
+    DoorClosed, //关着的门挡住视线，走进去就会打开
+    DoorOpen,
+    Water,  //浅水，走得慢
+    Lava,   //可以走，但每次踏入都会受伤
+    Rubble, //碎石，走得慢
 }
 
-pub fn map_idx(x: i32, y: i32) -> usize {
-    ((y * SCREEN_WIDTH) + x) as usize
+impl TileType {
+    pub fn walkable(self) -> bool {
+        self != TileType::Wall
+    }
+
+    pub fn opaque(self) -> bool {
+        matches!(self, TileType::Wall | TileType::DoorClosed)
+    }
+
+    /** 寻路时进入该地块的代价，岩浆代价很高，怪物会尽量绕开 */
+    pub fn movement_cost(self) -> f32 {
+        match self {
+            TileType::Rubble | TileType::DoorClosed => 2.0,
+            TileType::Water => 3.0,
+            TileType::Lava => 10.0,
+            TileType::Wall | TileType::Floor | TileType::Exit | TileType::DoorOpen => 1.0,
+        }
+    }
+
+    /** 进入该地块时受到的伤害 */
+    pub fn damage(self) -> i32 {
+        match self {
+            TileType::Lava => 2,
+            _ => 0,
+        }
+    }
+}
+
+/** 所有地图共用的版本号计数器，新地图和每次地形变化都取一个新值，
+ * 换了一层地图后缓存也不会把旧地图的版本号当成当前的 */
+static REVISIONS: AtomicU64 = AtomicU64::new(0);
+
+fn next_revision() -> u64 {
+    REVISIONS.fetch_add(1, Ordering::Relaxed)
 }
 
+#[derive(Clone, Serialize, Deserialize)]
 pub struct Map {
+    pub width: i32,
+    pub height: i32,
     pub tiles: Vec<TileType>,
     pub revealed_tiles: Vec<bool>,
+    #[serde(skip, default = "next_revision")]
+    revision: u64,
 }
 
 impl Map {
-    pub fn new() -> Self {
+    #[allow(clippy::cast_sign_loss)]
+    pub fn new(width: i32, height: i32) -> Self {
+        let num_tiles = (width * height) as usize;
         Self {
-            tiles: vec![TileType::Floor; NUM_TILES],
-            revealed_tiles: vec![false; NUM_TILES],
+            width,
+            height,
+            tiles: vec![TileType::Floor; num_tiles],
+            revealed_tiles: vec![false; num_tiles],
+            revision: next_revision(),
+        }
+    }
+
+    /** 地形的版本号，地形不变时保持不变，缓存的Dijkstra图据此判断是否需要重建 */
+    pub fn revision(&self) -> u64 {
+        self.revision
+    }
+
+    /** 游戏中改变地形都通过这里，例如开门 */
+    pub fn set_tile(&mut self, idx: usize, tile: TileType) {
+        if self.tiles[idx] != tile {
+            self.tiles[idx] = tile;
+            self.tiles_changed();
         }
     }
+
+    /** 直接改写了`tiles`之后调用，地图构建器成片地改写地形时使用 */
+    pub fn tiles_changed(&mut self) {
+        self.revision = next_revision();
+    }
+
+    #[allow(clippy::cast_sign_loss)]
+    pub fn idx(&self, x: i32, y: i32) -> usize {
+        ((y * self.width) + x) as usize
+    }
+
+    /** Dijkstra图的最大深度，足以覆盖整张地图上最长的路径，超过它的地块被视为不可达 */
+    #[allow(clippy::cast_precision_loss)]
+    pub fn max_path_depth(&self) -> f32 {
+        self.tiles.len() as f32 * TileType::Lava.movement_cost()
+    }
+
     /** 判断点是否在地图边界内 */
     pub fn in_bounds(&self, point: Point) -> bool {
-        point.x >= 0 && point.x < SCREEN_WIDTH && point.y >= 0 && point.y < SCREEN_HEIGHT
+        point.x >= 0 && point.x < self.width && point.y >= 0 && point.y < self.height
+    }
+
+    /** 地图最外圈，必须是墙，否则玩家可以走到地图边缘之外 */
+    pub fn is_border(&self, point: Point) -> bool {
+        point.x <= 0 || point.y <= 0 || point.x >= self.width - 1 || point.y >= self.height - 1
     }
 
     pub fn try_idx(&self, point: Point) -> Option<usize> {
-        if !self.in_bounds(point) {
-            None
+        if self.in_bounds(point) {
+            Some(self.idx(point.x, point.y))
         } else {
-            Some(map_idx(point.x, point.y))
+            None
         }
     }
 
     pub fn can_enter_tile(&self, point: Point) -> bool {
-        self.in_bounds(point) && self.tiles[map_idx(point.x, point.y)] == TileType::Floor
+        self.in_bounds(point) && self.tiles[self.idx(point.x, point.y)].walkable()
     }
 
-    fn valid_exit(&self, loc: Point, delta: Point) -> Option<usize> {
+    /** 可以进入时返回目标地块的索引和进入代价 */
+    fn valid_exit(&self, loc: Point, delta: Point) -> Option<(usize, f32)> {
         let destination = loc + delta;
         if self.in_bounds(destination) {
             if self.can_enter_tile(destination) {
                 let idx = self.point2d_to_index(destination);
-                Some(idx)
+                Some((idx, self.tiles[idx].movement_cost()))
             } else {
                 None
             }
@@ -58,7 +146,7 @@ impl Map {
 
 impl Algorithm2D for Map {
     fn dimensions(&self) -> Point {
-        Point::new(SCREEN_WIDTH, SCREEN_HEIGHT)
+        Point::new(self.width, self.height)
     }
     fn in_bounds(&self, point: Point) -> bool {
         self.in_bounds(point)
@@ -69,26 +157,85 @@ impl BaseMap for Map {
     fn get_available_exits(&self, idx: usize) -> SmallVec<[(usize, f32); 10]> {
         let mut exits = SmallVec::new();
         let location = self.index_to_point2d(idx);
-        if let Some(idx) = self.valid_exit(location, Point::new(-1, 0)) {
-            exits.push((idx, 1.0));
+        if let Some(exit) = self.valid_exit(location, Point::new(-1, 0)) {
+            exits.push(exit);
         }
-        if let Some(idx) = self.valid_exit(location, Point::new(1, 0)) {
-            exits.push((idx, 1.0));
+        if let Some(exit) = self.valid_exit(location, Point::new(1, 0)) {
+            exits.push(exit);
         }
-        if let Some(idx) = self.valid_exit(location, Point::new(0, -1)) {
-            exits.push((idx, 1.0));
+        if let Some(exit) = self.valid_exit(location, Point::new(0, -1)) {
+            exits.push(exit);
         }
-        if let Some(idx) = self.valid_exit(location, Point::new(0, 1)) {
-            exits.push((idx, 1.0));
+        if let Some(exit) = self.valid_exit(location, Point::new(0, 1)) {
+            exits.push(exit);
         }
         exits
     }
-    fn get_pathing_distance(&self, _idx1: usize, _idx2: usize) -> f32 {
+    fn get_pathing_distance(&self, idx1: usize, idx2: usize) -> f32 {
         DistanceAlg::Pythagoras
-            .distance2d(self.index_to_point2d(_idx1), self.index_to_point2d(_idx2))
+            .distance2d(self.index_to_point2d(idx1), self.index_to_point2d(idx2))
     }
 
     fn is_opaque(&self, idx: usize) -> bool {
-        self.tiles[idx] != TileType::Floor
+        self.tiles[idx].opaque()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tile_properties() {
+        use TileType::{DoorClosed, DoorOpen, Exit, Floor, Lava, Rubble, Wall, Water};
+        for tile in [Floor, Exit, DoorClosed, DoorOpen, Water, Lava, Rubble] {
+            assert!(tile.walkable(), "{tile:?}");
+        }
+        assert!(!Wall.walkable());
+        for tile in [Floor, Exit, DoorOpen, Water, Lava, Rubble] {
+            assert!(!tile.opaque(), "{tile:?}");
+        }
+        assert!(Wall.opaque() && DoorClosed.opaque());
+
+        assert!((Floor.movement_cost() - 1.0).abs() < f32::EPSILON);
+        assert!(Rubble.movement_cost() > Floor.movement_cost());
+        assert!(Water.movement_cost() > Rubble.movement_cost());
+        //岩浆的代价最高，寻路时尽量绕开
+        for tile in [Floor, Exit, DoorClosed, DoorOpen, Water, Rubble] {
+            assert!(Lava.movement_cost() > tile.movement_cost(), "{tile:?}");
+        }
+
+        assert!(Lava.damage() > 0);
+        for tile in [Wall, Floor, Exit, DoorClosed, DoorOpen, Water, Rubble] {
+            assert_eq!(tile.damage(), 0, "{tile:?}");
+        }
+    }
+
+    #[test]
+    fn exits_skip_walls_and_cost_the_destination_tile() {
+        let mut map = Map::new(5, 5);
+        let center = map.idx(2, 2);
+        let (left, right, up) = (map.idx(1, 2), map.idx(3, 2), map.idx(2, 1));
+        map.tiles[left] = TileType::Wall;
+        map.tiles[right] = TileType::Water;
+        map.tiles[up] = TileType::DoorClosed;
+        let exits = map.get_available_exits(center);
+        assert_eq!(exits.len(), 3);
+        assert!(!exits.iter().any(|(idx, _)| *idx == left));
+        for (idx, cost) in exits {
+            assert!((cost - map.tiles[idx].movement_cost()).abs() < f32::EPSILON);
+        }
+        assert!(map.is_opaque(up) && !map.is_opaque(right));
+    }
+
+    #[test]
+    fn revision_changes_only_with_the_terrain() {
+        let mut map = Map::new(5, 5);
+        let revision = map.revision();
+        map.set_tile(0, TileType::Floor);
+        assert_eq!(map.revision(), revision);
+        map.set_tile(0, TileType::Wall);
+        assert_ne!(map.revision(), revision);
+        assert_ne!(Map::new(5, 5).revision(), map.revision());
     }
 }
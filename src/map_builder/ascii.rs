@@ -0,0 +1,135 @@
+use crate::prelude::*;
+use std::fmt;
+
+/** 文本地图中的字符，与地窖相同，`#`为墙，`-`为地板，`M`为怪物出生点，另外：
+ * `@`为玩家起点，`A`为护身符或出口的位置，`>`为出口，
+ * `+`为关着的门，`'`为开着的门，`~`为水，`^`为岩浆，`:`为碎石 */
+const TILE_CHARS: [(char, TileType); 8] = [
+    ('#', TileType::Wall),
+    ('-', TileType::Floor),
+    ('>', TileType::Exit),
+    ('+', TileType::DoorClosed),
+    ('\'', TileType::DoorOpen),
+    ('~', TileType::Water),
+    ('^', TileType::Lava),
+    (':', TileType::Rubble),
+];
+
+#[derive(Debug)]
+pub struct LevelError(String);
+
+impl fmt::Display for LevelError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "文本地图无效: {}", self.0)
+    }
+}
+
+impl std::error::Error for LevelError {}
+
+impl MapBuilder {
+    /** 导出为文本地图，`from_ascii`可以原样读回 */
+    pub fn to_ascii(&self) -> String {
+        let mut text = String::new();
+        for y in 0..self.map.height {
+            for x in 0..self.map.width {
+                let pos = Point::new(x, y);
+                let tile = self.map.tiles[self.map.idx(x, y)];
+                let c = if pos == self.player_start {
+                    '@'
+                } else if pos == self.amulet_start && tile != TileType::Exit {
+                    'A'
+                } else if self.monster_spawns.contains(&pos) {
+                    'M'
+                } else {
+                    TILE_CHARS
+                        .iter()
+                        .find(|(_, t)| *t == tile)
+                        .map_or('#', |(c, _)| *c)
+                };
+                text.push(c);
+            }
+            text.push('\n');
+        }
+        text
+    }
+
+    /** 读取文本地图，每行去掉首尾空白，空行会被忽略。
+     * 必须有且只有一个`@`，`A`最多一个，没有时放在第一个`>`上，也没有`>`时放在离玩家最远的地方。
+     * 与生成的地图一样，最外圈必须是墙，`A`和所有`M`都必须能从`@`走到 */
+    pub fn from_ascii(text: &str) -> Result<Self, LevelError> {
+        let invalid = |reason: &str| LevelError(reason.to_string());
+        let rows: Vec<&str> = text
+            .lines()
+            .map(str::trim)
+            .filter(|row| !row.is_empty())
+            .collect();
+        let width = rows.first().map_or(0, |row| row.chars().count());
+        if rows.iter().any(|row| row.chars().count() != width) {
+            return Err(invalid("每一行的长度必须相同"));
+        }
+        let (Ok(width), Ok(height)) = (i32::try_from(width), i32::try_from(rows.len())) else {
+            return Err(invalid("地图太大"));
+        };
+        if width < MIN_MAP_WIDTH || height < MIN_MAP_HEIGHT {
+            return Err(invalid(&format!(
+                "地图至少要有{MIN_MAP_WIDTH}×{MIN_MAP_HEIGHT}"
+            )));
+        }
+
+        let mut mb = MapBuilder::empty(width, height);
+        let mut player_start = None;
+        let mut amulet_start = None;
+        for (y, row) in (0..).zip(&rows) {
+            for (x, c) in (0..).zip(row.chars()) {
+                let pos = Point::new(x, y);
+                let tile = match c {
+                    '@' if player_start.is_some() => return Err(invalid("只能有一个'@'")),
+                    'A' if amulet_start.is_some() => return Err(invalid("只能有一个'A'")),
+                    '@' => {
+                        player_start = Some(pos);
+                        TileType::Floor
+                    }
+                    'A' => {
+                        amulet_start = Some(pos);
+                        TileType::Floor
+                    }
+                    'M' => {
+                        mb.monster_spawns.push(pos);
+                        TileType::Floor
+                    }
+                    _ => TILE_CHARS
+                        .iter()
+                        .find(|(tc, _)| *tc == c)
+                        .map(|(_, t)| *t)
+                        .ok_or_else(|| invalid(&format!("未知字符'{c}'")))?,
+                };
+                let idx = mb.map.idx(x, y);
+                mb.map.tiles[idx] = tile;
+            }
+        }
+        if let Some(idx) = (0..mb.map.tiles.len()).find(|idx| {
+            mb.map.is_border(mb.map.index_to_point2d(*idx)) && mb.map.tiles[*idx] != TileType::Wall
+        }) {
+            let pos = mb.map.index_to_point2d(idx);
+            return Err(invalid(&format!("边界({}, {})必须是墙", pos.x, pos.y)));
+        }
+        mb.player_start = player_start.ok_or_else(|| invalid("缺少玩家起点'@'"))?;
+        let amulet_start = amulet_start.or_else(|| {
+            let exit = mb.map.tiles.iter().position(|t| *t == TileType::Exit)?;
+            Some(mb.map.index_to_point2d(exit))
+        });
+        let dijkstra_map = mb
+            .flow
+            .get(&mb.map, &[mb.map.point2d_to_index(mb.player_start)]);
+        let unreachable = amulet_start
+            .iter()
+            .chain(&mb.monster_spawns)
+            .find(|pos| dijkstra_map.map[mb.map.point2d_to_index(**pos)] >= f32::MAX)
+            .copied();
+        if let Some(pos) = unreachable {
+            return Err(invalid(&format!("从'@'走不到({}, {})", pos.x, pos.y)));
+        }
+        mb.amulet_start = amulet_start.unwrap_or_else(|| mb.find_most_distant());
+        Ok(mb)
+    }
+}
@@ -3,70 +3,56 @@ use crate::prelude::*;
 
 pub struct CellularAutomataArchitect {}
 
+/** 中心的洞穴至少占地图的1/5 */
+const MIN_FLOOR_DIVISOR: usize = 5;
+
 impl MapArchitect for CellularAutomataArchitect {
-    fn new(&mut self, rng: &mut RandomNumberGenerator) -> MapBuilder {
-        let mut mb = MapBuilder {
-            map: Map::new(),
-            rooms: Vec::new(),
-            monster_spawns: Vec::new(),
-            player_start: Point::zero(),
-            amulet_start: Point::zero(),
-        };
-        self.random_noise_map(rng, &mut mb.map);
+    fn build(&mut self, rng: &mut RandomNumberGenerator, mb: &mut MapBuilder) {
+        Self::random_noise_map(rng, &mut mb.map);
+        mb.take_snapshot();
         for _ in 0..10 {
-            self.iteration(&mut mb.map);
+            Self::iteration(&mut mb.map);
+            mb.take_snapshot();
         }
-        let start = self.find_start(&mb.map);
-        let dijkstra_map = DijkstraMap::new(
-            SCREEN_WIDTH,
-            SCREEN_HEIGHT,
-            &vec![mb.map.point2d_to_index(start)],
-            &mb.map,
-            1024.0,
-        );
-        // 把所有距离大于2000的点都设为墙，也就是删去他们，使地图成为一个连通图
-        dijkstra_map
-            .map
-            .iter()
-            .enumerate()
-            .filter(|(_, distance)| **distance > 2000.0)
-            .for_each(|(idx, _)| mb.map.tiles[idx] = TileType::Wall);
+    }
 
-        mb.monster_spawns = mb.spawn_monster(&start, rng);
-        mb.player_start = start;
-        mb.amulet_start = mb.find_most_distant();
-        mb //返回mb
+    fn min_floor_divisor(&self) -> Option<usize> {
+        Some(MIN_FLOOR_DIVISOR)
     }
 }
 
 impl CellularAutomataArchitect {
-    fn random_noise_map(&mut self, rng: &mut RandomNumberGenerator, map: &mut Map) {
-        map.tiles.iter_mut().for_each(|t| {
-            let roll = rng.range(1, 100);
-            if roll > 55 {
-                *t = TileType::Floor;
-            } else {
-                *t = TileType::Wall;
+    /** 随机生成噪声，边界始终是墙，迭代不会处理边界 */
+    fn random_noise_map(rng: &mut RandomNumberGenerator, map: &mut Map) {
+        for y in 0..map.height {
+            for x in 0..map.width {
+                let roll = rng.range(1, 100);
+                let idx = map.idx(x, y);
+                map.tiles[idx] = if roll > 55 && !map.is_border(Point::new(x, y)) {
+                    TileType::Floor
+                } else {
+                    TileType::Wall
+                };
             }
-        });
+        }
     }
-    fn count_neighbors(&mut self, x: i32, y: i32, map: &Map) -> usize {
+    fn count_neighbors(x: i32, y: i32, map: &Map) -> usize {
         let mut neighbors = 0;
         for iy in -1..=1 {
             for ix in -1..=1 {
-                if !(ix == 0 && iy == 0) && map.tiles[map_idx(x + ix, y + iy)] == TileType::Floor {
+                if !(ix == 0 && iy == 0) && map.tiles[map.idx(x + ix, y + iy)] == TileType::Floor {
                     neighbors += 1;
                 }
             }
         }
         neighbors
     }
-    fn iteration(&mut self, map: &mut Map) {
+    fn iteration(map: &mut Map) {
         let mut new_tiles = map.tiles.clone();
-        for y in 1..SCREEN_HEIGHT - 1 {
-            for x in 1..SCREEN_WIDTH - 1 {
-                let neighbors = self.count_neighbors(x, y, map);
-                let idx = map_idx(x, y);
+        for y in 1..map.height - 1 {
+            for x in 1..map.width - 1 {
+                let neighbors = Self::count_neighbors(x, y, map);
+                let idx = map.idx(x, y);
                 if neighbors == 0 || neighbors > 4 {
                     new_tiles[idx] = TileType::Wall;
                 } else {
@@ -76,23 +62,4 @@ impl CellularAutomataArchitect {
         }
         map.tiles = new_tiles;
     }
-    /** 找到地图中距离中心最近的一个地板点 */
-    fn find_start(&mut self, map: &Map) -> Point {
-        let center = Point::new(SCREEN_WIDTH / 2, SCREEN_HEIGHT / 2);
-        let closest_point = map
-            .tiles
-            .iter()
-            .enumerate()
-            .filter(|(_, t)| **t == TileType::Floor)
-            .map(|(idx, _)| {
-                (
-                    idx,
-                    DistanceAlg::Pythagoras.distance2d(center, map.index_to_point2d(idx)),
-                )
-            })
-            .min_by(|(_, distance_a), (_, distance_b)| distance_a.partial_cmp(distance_b).unwrap())
-            .map(|(idx, _)| idx)
-            .unwrap();
-        map.index_to_point2d(closest_point)
-    }
 }
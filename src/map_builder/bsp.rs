@@ -0,0 +1,104 @@
+use super::MapArchitect;
+use crate::prelude::*;
+
+/** 默认尺寸地图上尝试放置房间的次数 */
+const BSP_ATTEMPTS: usize = 240;
+
+/** 二叉空间分割：不断把空间切成四块，在其中随机放置不重叠的房间 */
+pub struct BspArchitect {
+    rects: Vec<Rect>,
+}
+
+impl BspArchitect {
+    pub fn new() -> Self {
+        Self { rects: Vec::new() }
+    }
+}
+
+impl MapArchitect for BspArchitect {
+    fn build(&mut self, rng: &mut RandomNumberGenerator, mb: &mut MapBuilder) {
+        mb.fill(TileType::Wall);
+        self.rects.clear();
+        let first = Rect::with_size(2, 2, mb.map.width - 5, mb.map.height - 5);
+        self.rects.push(first);
+        self.add_subrects(first);
+
+        for _ in 0..mb.scaled(BSP_ATTEMPTS).max(BSP_ATTEMPTS / 4) {
+            let Some(rect) = rng.random_slice_entry(&self.rects).copied() else {
+                break;
+            };
+            let candidate = Self::random_sub_rect(rect, rng);
+            if Self::is_possible(candidate, &mb.map) {
+                candidate.for_each(|pos| {
+                    let idx = mb.map.point2d_to_index(pos);
+                    mb.map.tiles[idx] = TileType::Floor;
+                });
+                mb.rooms.push(candidate);
+                mb.take_snapshot();
+                self.add_subrects(rect);
+            }
+        }
+        // 地图太小一个房间也放不下时，在中心挖一个小房间
+        if mb.rooms.is_empty() {
+            let center = mb.center();
+            let room = Rect::with_size(center.x - 2, center.y - 2, 4, 4);
+            room.for_each(|pos| {
+                let idx = mb.map.point2d_to_index(pos);
+                mb.map.tiles[idx] = TileType::Floor;
+            });
+            mb.rooms.push(room);
+        }
+    }
+}
+
+impl BspArchitect {
+    /** 把一块区域平分成四块 */
+    fn add_subrects(&mut self, rect: Rect) {
+        let half_width = i32::max(rect.width() / 2, 1);
+        let half_height = i32::max(rect.height() / 2, 1);
+        self.rects
+            .push(Rect::with_size(rect.x1, rect.y1, half_width, half_height));
+        self.rects.push(Rect::with_size(
+            rect.x1,
+            rect.y1 + half_height,
+            half_width,
+            half_height,
+        ));
+        self.rects.push(Rect::with_size(
+            rect.x1 + half_width,
+            rect.y1,
+            half_width,
+            half_height,
+        ));
+        self.rects.push(Rect::with_size(
+            rect.x1 + half_width,
+            rect.y1 + half_height,
+            half_width,
+            half_height,
+        ));
+    }
+
+    /** 在区域内随机取一个3到10格见方的房间 */
+    fn random_sub_rect(rect: Rect, rng: &mut RandomNumberGenerator) -> Rect {
+        let width = i32::max(3, rng.roll_dice(1, i32::min(rect.width().max(1), 10)) - 1) + 1;
+        let height = i32::max(3, rng.roll_dice(1, i32::min(rect.height().max(1), 10)) - 1) + 1;
+        Rect::with_size(
+            rect.x1 + rng.roll_dice(1, 6) - 1,
+            rect.y1 + rng.roll_dice(1, 6) - 1,
+            width,
+            height,
+        )
+    }
+
+    /** 房间向外扩两格后仍在地图内部，并且全是墙，才能放下 */
+    fn is_possible(rect: Rect, map: &Map) -> bool {
+        let expanded = Rect::with_exact(rect.x1 - 2, rect.y1 - 2, rect.x2 + 2, rect.y2 + 2);
+        let mut can_build = true;
+        expanded.for_each(|pos| {
+            if map.is_border(pos) || map.tiles[map.point2d_to_index(pos)] != TileType::Wall {
+                can_build = false;
+            }
+        });
+        can_build
+    }
+}
@@ -0,0 +1,64 @@
+use super::MapArchitect;
+use crate::prelude::*;
+
+/** 房间的最小边长，小于两倍时不再分割 */
+const MIN_ROOM_SIZE: i32 = 6;
+
+/** 把整张地图像建筑一样分割成紧挨着的房间，房间之间只隔一堵墙 */
+pub struct BspInteriorArchitect {}
+
+impl MapArchitect for BspInteriorArchitect {
+    fn build(&mut self, rng: &mut RandomNumberGenerator, mb: &mut MapBuilder) {
+        mb.fill(TileType::Wall);
+        let whole = Rect::with_size(1, 1, mb.map.width - 2, mb.map.height - 2);
+        Self::split(whole, rng, &mut mb.rooms);
+        for room in mb.rooms.clone() {
+            room.for_each(|pos| {
+                let idx = mb.map.point2d_to_index(pos);
+                mb.map.tiles[idx] = TileType::Floor;
+            });
+            mb.take_snapshot();
+        }
+    }
+}
+
+impl BspInteriorArchitect {
+    /** 递归地沿随机位置切成两半，中间留一格作为墙，切不动的区域就是房间 */
+    fn split(rect: Rect, rng: &mut RandomNumberGenerator, rooms: &mut Vec<Rect>) {
+        let can_split_x = rect.width() > MIN_ROOM_SIZE * 2;
+        let can_split_y = rect.height() > MIN_ROOM_SIZE * 2;
+        let split_x = match (can_split_x, can_split_y) {
+            (false, false) => {
+                rooms.push(rect);
+                return;
+            }
+            (true, true) => rng.range(0, 2) == 0,
+            (split_x, _) => split_x,
+        };
+        if split_x {
+            let at = rng.range(MIN_ROOM_SIZE, rect.width() - MIN_ROOM_SIZE);
+            Self::split(
+                Rect::with_size(rect.x1, rect.y1, at, rect.height()),
+                rng,
+                rooms,
+            );
+            Self::split(
+                Rect::with_exact(rect.x1 + at + 1, rect.y1, rect.x2, rect.y2),
+                rng,
+                rooms,
+            );
+        } else {
+            let at = rng.range(MIN_ROOM_SIZE, rect.height() - MIN_ROOM_SIZE);
+            Self::split(
+                Rect::with_size(rect.x1, rect.y1, rect.width(), at),
+                rng,
+                rooms,
+            );
+            Self::split(
+                Rect::with_exact(rect.x1, rect.y1 + at + 1, rect.x2, rect.y2),
+                rng,
+                rooms,
+            );
+        }
+    }
+}
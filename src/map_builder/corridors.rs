@@ -0,0 +1,103 @@
+use super::MetaMapBuilder;
+use crate::prelude::*;
+
+/** 走廊的形状：先横后竖（或先竖后横）的折线，或者尽量沿直线挖过去 */
+#[derive(Clone, Copy)]
+pub enum CorridorStyle {
+    Dogleg,
+    Straight,
+}
+
+impl CorridorStyle {
+    pub fn random(rng: &mut RandomNumberGenerator) -> Self {
+        if rng.range(0, 2) == 0 {
+            Self::Dogleg
+        } else {
+            Self::Straight
+        }
+    }
+}
+
+/** 最初的连法：按房间中心的x坐标排序，依次用折线走廊连起来 */
+pub struct SortedCorridors;
+
+impl MetaMapBuilder for SortedCorridors {
+    fn build(&mut self, rng: &mut RandomNumberGenerator, mb: &mut MapBuilder) {
+        let mut centers: Vec<Point> = mb.rooms.iter().map(Rect::center).collect();
+        centers.sort_by_key(|center| center.x);
+        for pair in centers.windows(2) {
+            mb.apply_dogleg_tunnel(pair[0], pair[1], rng);
+            mb.take_snapshot();
+        }
+    }
+}
+
+/** 每次把离已连通部分最近的房间连进来，保证所有房间连通，走廊也更短 */
+pub struct NearestCorridors(pub CorridorStyle);
+
+impl MetaMapBuilder for NearestCorridors {
+    fn build(&mut self, rng: &mut RandomNumberGenerator, mb: &mut MapBuilder) {
+        let centers: Vec<Point> = mb.rooms.iter().map(Rect::center).collect();
+        if centers.is_empty() {
+            return;
+        }
+        let mut connected = vec![false; centers.len()];
+        connected[0] = true;
+        for _ in 1..centers.len() {
+            let nearest = (0..centers.len())
+                .filter(|from| connected[*from])
+                .flat_map(|from| {
+                    (0..centers.len())
+                        .filter(|to| !connected[*to])
+                        .map(move |to| (from, to))
+                })
+                .min_by(|(a, b), (c, d)| {
+                    let first = DistanceAlg::Pythagoras.distance2d(centers[*a], centers[*b]);
+                    let second = DistanceAlg::Pythagoras.distance2d(centers[*c], centers[*d]);
+                    first.partial_cmp(&second).unwrap()
+                });
+            let Some((from, to)) = nearest else {
+                break;
+            };
+            connected[to] = true;
+            match self.0 {
+                CorridorStyle::Dogleg => mb.apply_dogleg_tunnel(centers[from], centers[to], rng),
+                CorridorStyle::Straight => mb.apply_straight_tunnel(centers[from], centers[to]),
+            }
+            mb.take_snapshot();
+        }
+    }
+}
+
+impl MapBuilder {
+    /** L形走廊，随机决定先横后竖还是先竖后横 */
+    fn apply_dogleg_tunnel(&mut self, from: Point, to: Point, rng: &mut RandomNumberGenerator) {
+        if rng.range(0, 2) == 1 {
+            self.apply_horizontal_tunnel(from.x, to.x, from.y);
+            self.apply_vertical_tunnel(from.y, to.y, to.x);
+        } else {
+            self.apply_vertical_tunnel(from.y, to.y, from.x);
+            self.apply_horizontal_tunnel(from.x, to.x, to.y);
+        }
+    }
+
+    /** 沿Bresenham直线挖走廊，斜着走的地方补一格，保证上下左右可以走通 */
+    fn apply_straight_tunnel(&mut self, from: Point, to: Point) {
+        let mut last = from;
+        self.carve(from);
+        for pos in line2d_bresenham(from, to) {
+            if pos.x != last.x && pos.y != last.y {
+                self.carve(Point::new(pos.x, last.y));
+            }
+            self.carve(pos);
+            last = pos;
+        }
+    }
+
+    fn carve(&mut self, pos: Point) {
+        if !self.map.is_border(pos) {
+            let idx = self.map.point2d_to_index(pos);
+            self.map.set_tile(idx, TileType::Floor);
+        }
+    }
+}
@@ -0,0 +1,106 @@
+use super::MapArchitect;
+use crate::prelude::*;
+
+/** 地板占地图的比例的倒数 */
+const FLOOR_DIVISOR: usize = 4;
+/** 每粘上多少个粒子记录一次地图 */
+const SNAPSHOT_INTERVAL: usize = 50;
+
+/** 游走粒子的走法 */
+#[derive(Clone, Copy)]
+pub enum DlaStyle {
+    /** 从随机位置出发随机游走，碰到地板就停下 */
+    WalkInwards,
+    /** 从随机位置出发沿直线走向中心，碰到地板就停下 */
+    CentralAttractor,
+}
+
+impl DlaStyle {
+    pub fn random(rng: &mut RandomNumberGenerator) -> Self {
+        if rng.range(0, 2) == 0 {
+            Self::WalkInwards
+        } else {
+            Self::CentralAttractor
+        }
+    }
+}
+
+/** 扩散限制凝聚：粒子一个个粘到已有的地板上，长出枝杈状的洞穴，每一步都和中心连通 */
+pub struct DlaArchitect(pub DlaStyle);
+
+impl MapArchitect for DlaArchitect {
+    fn build(&mut self, rng: &mut RandomNumberGenerator, mb: &mut MapBuilder) {
+        mb.fill(TileType::Wall);
+        let center = mb.center();
+        for offset in [(0, 0), (-1, 0), (1, 0), (0, -1), (0, 1)] {
+            let idx = mb
+                .map
+                .point2d_to_index(center + Point::new(offset.0, offset.1));
+            mb.map.tiles[idx] = TileType::Floor;
+        }
+        let desired_floor = mb.map.tiles.len() / FLOOR_DIVISOR;
+        let mut floor = mb.floor_count();
+        while floor < desired_floor {
+            let start = Point::new(
+                rng.range(1, mb.map.width - 1),
+                rng.range(1, mb.map.height - 1),
+            );
+            if mb.map.tiles[mb.map.point2d_to_index(start)] == TileType::Floor {
+                continue;
+            }
+            let stuck = match self.0 {
+                DlaStyle::WalkInwards => Self::walk_inwards(start, rng, &mb.map),
+                DlaStyle::CentralAttractor => Self::central_attractor(start, center, &mb.map),
+            };
+            let idx = mb.map.point2d_to_index(stuck);
+            mb.map.tiles[idx] = TileType::Floor;
+            floor += 1;
+            if floor.is_multiple_of(SNAPSHOT_INTERVAL) {
+                mb.take_snapshot();
+            }
+        }
+    }
+}
+
+impl DlaArchitect {
+    /** 随机游走到一块紧挨地板的墙上，不会走到边界 */
+    fn walk_inwards(start: Point, rng: &mut RandomNumberGenerator, map: &Map) -> Point {
+        let mut pos = start;
+        loop {
+            let mut next = pos;
+            match rng.range(0, 4) {
+                0 => next.x -= 1,
+                1 => next.x += 1,
+                2 => next.y -= 1,
+                _ => next.y += 1,
+            }
+            if map.is_border(next) {
+                continue;
+            }
+            if map.tiles[map.point2d_to_index(next)] == TileType::Floor {
+                return pos;
+            }
+            pos = next;
+        }
+    }
+
+    /** 沿直线走向中心，停在碰到的第一块地板前面 */
+    fn central_attractor(start: Point, center: Point, map: &Map) -> Point {
+        let mut pos = start;
+        for next in line2d_bresenham(start, center) {
+            //斜着走时先横着走一步，保证停下的位置和地板上下左右相邻
+            let corner = Point::new(next.x, pos.y);
+            if corner != pos && corner != next {
+                if map.tiles[map.point2d_to_index(corner)] == TileType::Floor {
+                    return pos;
+                }
+                pos = corner;
+            }
+            if map.tiles[map.point2d_to_index(next)] == TileType::Floor {
+                return pos;
+            }
+            pos = next;
+        }
+        pos
+    }
+}
@@ -4,57 +4,29 @@ use crate::prelude::*;
 pub struct DrunkarksWalkArchitect {}
 
 const STAGGER_DISTANCE: usize = 400;
-const NUM_TILES: usize = (SCREEN_WIDTH * SCREEN_HEIGHT) as usize;
-const DESIRED_FLOOR: usize = NUM_TILES / 3;
 
 impl MapArchitect for DrunkarksWalkArchitect {
-    fn new(&mut self, rng: &mut RandomNumberGenerator) -> MapBuilder {
-        let mut mb = MapBuilder {
-            map: Map::new(),
-            rooms: Vec::new(),
-            monster_spawns: Vec::new(),
-            player_start: Point::zero(),
-            amulet_start: Point::zero(),
-        };
+    fn build(&mut self, rng: &mut RandomNumberGenerator, mb: &mut MapBuilder) {
         mb.fill(TileType::Wall);
-        let center = Point::new(SCREEN_WIDTH / 2, SCREEN_HEIGHT / 2);
-        self.drunkark(&center, rng, &mut mb.map);
-        while mb
-            .map
-            .tiles
-            .iter()
-            .filter(|t| **t == TileType::Floor)
-            .count()
-            < DESIRED_FLOOR
-        {
-            self.drunkark(
-                &Point::new(rng.range(0, SCREEN_WIDTH), rng.range(0, SCREEN_HEIGHT)),
+        let center = mb.center();
+        let desired_floor = mb.map.tiles.len() / 3;
+        Self::drunkark(center, rng, &mut mb.map);
+        mb.take_snapshot();
+        while mb.floor_count() < desired_floor {
+            Self::drunkark(
+                Point::new(rng.range(1, mb.map.width - 1), rng.range(1, mb.map.height - 1)),
                 rng,
                 &mut mb.map,
             );
-            let dijkstra_map = DijkstraMap::new(
-                SCREEN_WIDTH,
-                SCREEN_HEIGHT,
-                &vec![mb.map.point2d_to_index(center)],
-                &mb.map,
-                1024.0,
-            );
-            dijkstra_map
-                .map
-                .iter()
-                .enumerate()
-                .filter(|(_, distance)| **distance > 2000.0)
-                .for_each(|(idx, _)| mb.map.tiles[idx] = TileType::Wall);
+            //只统计与中心连通的地板
+            mb.cull_unreachable(center);
+            mb.take_snapshot();
         }
-        mb.monster_spawns = mb.spawn_monster(&center, rng);
-        mb.player_start = center;
-        mb.amulet_start = mb.find_most_distant();
-        mb //返回mb
     }
 }
 impl DrunkarksWalkArchitect {
-    fn drunkark(&mut self, start: &Point, rng: &mut RandomNumberGenerator, map: &mut Map) {
-        let mut drunkard_pos = start.clone();
+    fn drunkark(start: Point, rng: &mut RandomNumberGenerator, map: &mut Map) {
+        let mut drunkard_pos = start;
         let mut distance_staggered: usize = 0;
         loop {
             let drunk_idx = map.point2d_to_index(drunkard_pos);
@@ -65,7 +37,8 @@ impl DrunkarksWalkArchitect {
                 2 => drunkard_pos.y -= 1,
                 _ => drunkard_pos.y += 1,
             }
-            if !map.in_bounds(drunkard_pos) {
+            //走到边界就停下，保证地图四周是墙
+            if map.is_border(drunkard_pos) {
                 break;
             }
             distance_staggered += 1;
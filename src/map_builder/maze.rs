@@ -0,0 +1,73 @@
+use super::MapArchitect;
+use crate::prelude::*;
+
+/** 每多少个格子额外打通一堵墙，让迷宫出现环路，不至于全是死胡同 */
+const LOOP_DIVISOR: usize = 12;
+/** 每打通多少个格子记录一次地图 */
+const SNAPSHOT_INTERVAL: usize = 40;
+
+/** 递归回溯迷宫：奇数坐标是格子，格子之间的墙被打通就成了通道 */
+pub struct MazeArchitect {}
+
+impl MapArchitect for MazeArchitect {
+    fn build(&mut self, rng: &mut RandomNumberGenerator, mb: &mut MapBuilder) {
+        mb.fill(TileType::Wall);
+        let columns = (mb.map.width - 1) / 2;
+        let rows = (mb.map.height - 1) / 2;
+        let cell = |x: i32, y: i32| Point::new(x * 2 + 1, y * 2 + 1);
+        let mut visited = vec![false; usize::try_from(columns * rows).unwrap_or(0)];
+        let visit_idx = |pos: Point| usize::try_from(pos.y * columns + pos.x).unwrap_or(0);
+
+        //用栈代替递归，避免大地图上栈溢出
+        let mut stack = vec![Point::new(columns / 2, rows / 2)];
+        visited[visit_idx(stack[0])] = true;
+        Self::carve(&mut mb.map, cell(columns / 2, rows / 2));
+        let mut carved: usize = 1;
+        while let Some(&current) = stack.last() {
+            let neighbors: Vec<Point> = [(0, -1), (0, 1), (-1, 0), (1, 0)]
+                .iter()
+                .map(|(dx, dy)| current + Point::new(*dx, *dy))
+                .filter(|pos| pos.x >= 0 && pos.x < columns && pos.y >= 0 && pos.y < rows)
+                .filter(|pos| !visited[visit_idx(*pos)])
+                .collect();
+            let Some(&next) = rng.random_slice_entry(&neighbors) else {
+                stack.pop();
+                continue;
+            };
+            visited[visit_idx(next)] = true;
+            let from = cell(current.x, current.y);
+            let to = cell(next.x, next.y);
+            Self::carve(
+                &mut mb.map,
+                Point::new(i32::midpoint(from.x, to.x), i32::midpoint(from.y, to.y)),
+            );
+            Self::carve(&mut mb.map, to);
+            stack.push(next);
+            carved += 1;
+            if carved.is_multiple_of(SNAPSHOT_INTERVAL) {
+                mb.take_snapshot();
+            }
+        }
+
+        //随机打通一些格子之间的墙，形成环路
+        for _ in 0..visited.len() / LOOP_DIVISOR {
+            let x = rng.range(1, mb.map.width - 1);
+            let y = rng.range(1, mb.map.height - 1);
+            let pos = Point::new(x, y);
+            let horizontal = mb.map.tiles[mb.map.idx(x - 1, y)] == TileType::Floor
+                && mb.map.tiles[mb.map.idx(x + 1, y)] == TileType::Floor;
+            let vertical = mb.map.tiles[mb.map.idx(x, y - 1)] == TileType::Floor
+                && mb.map.tiles[mb.map.idx(x, y + 1)] == TileType::Floor;
+            if horizontal != vertical {
+                Self::carve(&mut mb.map, pos);
+            }
+        }
+    }
+}
+
+impl MazeArchitect {
+    fn carve(map: &mut Map, pos: Point) {
+        let idx = map.point2d_to_index(pos);
+        map.tiles[idx] = TileType::Floor;
+    }
+}
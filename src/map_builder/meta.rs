@@ -0,0 +1,205 @@
+use super::MetaMapBuilder;
+use crate::prelude::*;
+
+/** 默认尺寸地图上的地形池数量 */
+const NUM_POOLS: usize = 6;
+/** 地形池的最大半径 */
+const MAX_POOL_RADIUS: i32 = 2;
+
+/** 把玩家放在离地图中心最近的地板上 */
+pub struct CenterStart;
+
+impl MetaMapBuilder for CenterStart {
+    fn build(&mut self, _rng: &mut RandomNumberGenerator, mb: &mut MapBuilder) {
+        let center = mb.center();
+        mb.player_start = mb.nearest_floor(center).unwrap_or(center);
+    }
+}
+
+/** 把玩家放在第一个房间的中心 */
+pub struct RoomStart;
+
+impl MetaMapBuilder for RoomStart {
+    fn build(&mut self, _rng: &mut RandomNumberGenerator, mb: &mut MapBuilder) {
+        if let Some(room) = mb.rooms.first() {
+            mb.player_start = room.center();
+        }
+    }
+}
+
+/** 删去从玩家起点不可达的区域 */
+pub struct CullUnreachable;
+
+impl MetaMapBuilder for CullUnreachable {
+    fn build(&mut self, _rng: &mut RandomNumberGenerator, mb: &mut MapBuilder) {
+        mb.cull_unreachable(mb.player_start);
+    }
+}
+
+/** 把护身符（或楼梯）放在离起点最远的可达位置 */
+pub struct DistantExit;
+
+impl MetaMapBuilder for DistantExit {
+    fn build(&mut self, _rng: &mut RandomNumberGenerator, mb: &mut MapBuilder) {
+        mb.amulet_start = mb.find_most_distant();
+    }
+}
+
+/** 在护身符的位置放上通往下一层的楼梯，最深一层放的是护身符，不加这一步 */
+pub struct ExitStairs;
+
+impl MetaMapBuilder for ExitStairs {
+    fn build(&mut self, _rng: &mut RandomNumberGenerator, mb: &mut MapBuilder) {
+        mb.place_exit();
+    }
+}
+
+/** 最后一步：清理不合理的怪物出生点 */
+pub struct PruneSpawns;
+
+impl MetaMapBuilder for PruneSpawns {
+    fn build(&mut self, _rng: &mut RandomNumberGenerator, mb: &mut MapBuilder) {
+        mb.prune_spawns();
+    }
+}
+
+/** 在离起点较远的地板上随机分布怪物 */
+pub struct RandomSpawns;
+
+impl MetaMapBuilder for RandomSpawns {
+    fn build(&mut self, rng: &mut RandomNumberGenerator, mb: &mut MapBuilder) {
+        mb.spawn_monsters(rng);
+    }
+}
+
+/** 除第一个房间外，每个房间的中心放一只怪物 */
+pub struct RoomSpawns;
+
+impl MetaMapBuilder for RoomSpawns {
+    fn build(&mut self, _rng: &mut RandomNumberGenerator, mb: &mut MapBuilder) {
+        let centers: Vec<Point> = mb.rooms.iter().skip(1).map(Rect::center).collect();
+        for center in centers {
+            if center != mb.player_start && !mb.monster_spawns.contains(&center) {
+                mb.monster_spawns.push(center);
+            }
+        }
+    }
+}
+
+/** 在走廊进入房间的地方装上关着的门，地窖会覆盖掉它范围内的门 */
+pub struct AddDoors;
+
+impl MetaMapBuilder for AddDoors {
+    fn build(&mut self, _rng: &mut RandomNumberGenerator, mb: &mut MapBuilder) {
+        let mut doors = Vec::new();
+        for room in &mb.rooms {
+            //房间外面紧挨着的一圈
+            let ring = (room.x1 - 1..=room.x2)
+                .flat_map(|x| [Point::new(x, room.y1 - 1), Point::new(x, room.y2)])
+                .chain(
+                    (room.y1..room.y2)
+                        .flat_map(|y| [Point::new(room.x1 - 1, y), Point::new(room.x2, y)]),
+                );
+            doors.extend(ring.filter(|pos| Self::is_doorway(&mb.map, *pos)));
+        }
+        for pos in doors {
+            //相邻的两扇门只保留一扇
+            let next_to_door = [(-1, 0), (1, 0), (0, -1), (0, 1)].iter().any(|(dx, dy)| {
+                mb.map
+                    .try_idx(pos + Point::new(*dx, *dy))
+                    .is_some_and(|idx| mb.map.tiles[idx] == TileType::DoorClosed)
+            });
+            if !next_to_door && pos != mb.player_start {
+                let idx = mb.map.point2d_to_index(pos);
+                mb.map.set_tile(idx, TileType::DoorClosed);
+            }
+        }
+    }
+}
+
+impl AddDoors {
+    /** 两侧是墙、前后是地板的狭窄通道 */
+    fn is_doorway(map: &Map, pos: Point) -> bool {
+        let tile = |dx: i32, dy: i32| {
+            map.try_idx(pos + Point::new(dx, dy))
+                .map_or(TileType::Wall, |idx| map.tiles[idx])
+        };
+        if tile(0, 0) != TileType::Floor {
+            return false;
+        }
+        let walls = |a: TileType, b: TileType| a == TileType::Wall && b == TileType::Wall;
+        let floors = |a: TileType, b: TileType| a.walkable() && b.walkable();
+        (walls(tile(-1, 0), tile(1, 0)) && floors(tile(0, -1), tile(0, 1)))
+            || (walls(tile(0, -1), tile(0, 1)) && floors(tile(-1, 0), tile(1, 0)))
+    }
+}
+
+/** 在地板上铺几片水塘、岩浆池或碎石堆，地形由主题决定。
+ * 不覆盖玩家、护身符和怪物出生点，会伤人的地形不能挡住原本不必踩上去就能到达的地方 */
+pub struct TerrainPools(pub TileType);
+
+impl MetaMapBuilder for TerrainPools {
+    fn build(&mut self, rng: &mut RandomNumberGenerator, mb: &mut MapBuilder) {
+        let tile = self.0;
+        let count = mb.scaled(NUM_POOLS).max(1);
+        for _ in 0..count {
+            let floors: Vec<Point> = mb
+                .map
+                .tiles
+                .iter()
+                .enumerate()
+                .filter(|(_, t)| **t == TileType::Floor)
+                .map(|(idx, _)| mb.map.index_to_point2d(idx))
+                .collect();
+            let Some(center) = rng.random_slice_entry(&floors).copied() else {
+                return;
+            };
+            let radius = rng.range(1, MAX_POOL_RADIUS + 1);
+            #[allow(clippy::cast_precision_loss)]
+            let pool: Vec<usize> = floors
+                .iter()
+                .filter(|pos| DistanceAlg::Pythagoras.distance2d(center, **pos) <= radius as f32)
+                .filter(|pos| {
+                    **pos != mb.player_start
+                        && **pos != mb.amulet_start
+                        && !mb.monster_spawns.contains(pos)
+                })
+                .map(|pos| mb.map.point2d_to_index(*pos))
+                .collect();
+            if tile.damage() > 0 && Self::cuts_off(mb, &pool) {
+                continue;
+            }
+            for idx in pool {
+                mb.map.set_tile(idx, tile);
+            }
+        }
+    }
+}
+
+impl TerrainPools {
+    /** 把会伤人的地形都当成墙，铺上这片地形后是否有原本可达的地块变得不可达 */
+    fn cuts_off(mb: &MapBuilder, pool: &[usize]) -> bool {
+        let reachable = |map: &Map| {
+            let mut safe = map.clone();
+            safe.tiles
+                .iter_mut()
+                .filter(|t| t.damage() > 0)
+                .for_each(|t| *t = TileType::Wall);
+            let start = [safe.point2d_to_index(mb.player_start)];
+            DijkstraMap::new(safe.width, safe.height, &start, &safe, safe.max_path_depth()).map
+        };
+        let before = reachable(&mb.map);
+        let mut with_pool = mb.map.clone();
+        for idx in pool {
+            with_pool.tiles[*idx] = TileType::Wall;
+        }
+        let after = reachable(&with_pool);
+        before
+            .iter()
+            .zip(&after)
+            .enumerate()
+            .any(|(idx, (before, after))| {
+                *before < f32::MAX && *after >= f32::MAX && !pool.contains(&idx)
+            })
+    }
+}
@@ -8,94 +8,449 @@ mod automatas;
 use automatas::CellularAutomataArchitect;
 mod drunkark;
 use drunkark::DrunkarksWalkArchitect;
+mod bsp;
+use bsp::BspArchitect;
+mod bsp_interior;
+use bsp_interior::BspInteriorArchitect;
+mod maze;
+use maze::MazeArchitect;
+mod voronoi;
+use voronoi::VoronoiArchitect;
+mod dla;
+use dla::{DlaArchitect, DlaStyle};
+mod wfc;
+use wfc::{WfcArchitect, WfcSource};
+mod corridors;
+use corridors::{CorridorStyle, NearestCorridors, SortedCorridors};
+mod meta;
+use meta::{
+    AddDoors, CenterStart, CullUnreachable, DistantExit, ExitStairs, PruneSpawns, RandomSpawns,
+    RoomSpawns, RoomStart, TerrainPools,
+};
 mod predab;
+use predab::ApplyVault;
+pub use predab::Vaults;
+mod ascii;
+mod themes;
+pub use themes::*;
+#[cfg(test)]
+mod tests;
+
+/** 构建链的第一步，只负责在空白地图上生成地形 */
 trait MapArchitect {
-    fn new(&mut self, rng: &mut RandomNumberGenerator) -> MapBuilder;
+    fn build(&mut self, rng: &mut RandomNumberGenerator, mb: &mut MapBuilder);
+
+    /** 生成的地形可能不连通时返回`Some(n)`：只保留离中心最近的地板所在的区域，
+     * 它的地板少于地图的1/n时重新生成，见`MapBuilder::build_terrain` */
+    fn min_floor_divisor(&self) -> Option<usize> {
+        None
+    }
+}
+
+/** 构建链中的后续步骤，在已有地图上做加工，可以自由组合。
+ * 直接改写`mb.map.tiles`的步骤要调用`mb.map.tiles_changed()`，否则后面的步骤会用到过期的距离 */
+trait MetaMapBuilder {
+    fn build(&mut self, rng: &mut RandomNumberGenerator, mb: &mut MapBuilder);
+}
+
+/** 一个初始构建器加上若干后续步骤，新的构建器只需要实现与众不同的那一部分 */
+struct BuilderChain {
+    architect: Box<dyn MapArchitect>,
+    steps: Vec<Box<dyn MetaMapBuilder>>,
+}
+
+impl BuilderChain {
+    fn new(architect: impl MapArchitect + 'static) -> Self {
+        Self {
+            architect: Box::new(architect),
+            steps: Vec::new(),
+        }
+    }
+
+    fn with(mut self, step: impl MetaMapBuilder + 'static) -> Self {
+        self.steps.push(Box::new(step));
+        self
+    }
+
+    /** 常用的步骤：起点放在中心附近，删去不可达区域，随机分布怪物，放置地窖，终点放在最远处，最后清理出生点 */
+    fn standard(architect: impl MapArchitect + 'static, vaults: &Vaults) -> Self {
+        Self::new(architect)
+            .with(CenterStart)
+            .with(CullUnreachable)
+            .with(RandomSpawns)
+            .with(ApplyVault::new(vaults))
+            .with(DistantExit)
+            .with(PruneSpawns)
+    }
+
+    /** 最初的房间地图：随机房间按x坐标依次连接 */
+    fn rooms(vaults: &Vaults) -> Self {
+        Self::room_chain(RoomArchitect {}, SortedCorridors, vaults)
+    }
+
+    /** 房间地图：连好走廊后从第一个房间出发，怪物放在其余房间的中心，房间入口装上门 */
+    fn room_chain(
+        architect: impl MapArchitect + 'static,
+        corridors: impl MetaMapBuilder + 'static,
+        vaults: &Vaults,
+    ) -> Self {
+        Self::new(architect)
+            .with(corridors)
+            .with(RoomStart)
+            .with(RoomSpawns)
+            .with(AddDoors)
+            .with(ApplyVault::new(vaults))
+            .with(DistantExit)
+            .with(PruneSpawns)
+    }
+
+    /** 尺寸至少为`MIN_MAP_WIDTH`×`MIN_MAP_HEIGHT`，`record_history`为true时记录各个阶段的快照 */
+    fn build(
+        mut self,
+        rng: &mut RandomNumberGenerator,
+        width: i32,
+        height: i32,
+        record_history: bool,
+    ) -> MapBuilder {
+        let mut mb = MapBuilder::empty(width.max(MIN_MAP_WIDTH), height.max(MIN_MAP_HEIGHT));
+        mb.record_history = record_history;
+        mb.build_terrain(rng, self.architect.as_mut());
+        mb.map.tiles_changed();
+        mb.take_snapshot();
+        for step in &mut self.steps {
+            step.build(rng, &mut mb);
+            mb.take_snapshot();
+        }
+        mb
+    }
 }
 
+/** 每一层可以选用的构建链编号，越深的层地形越不规则，比表格更深的层使用最后一行。
+ * 0洞穴 1房间 2空地 3房间+最近走廊 4 BSP 5 BSP内部 6迷宫 7 Voronoi 8 DLA 9 WFC 10醉汉漫步 */
+const LEVEL_CHAINS: [&[i32]; DUNGEON_DEPTH as usize] = [
+    &[1, 3, 4, 5, 10],
+    &[0, 3, 4, 7, 9, 10],
+    &[0, 2, 6, 7, 8, 9, 10],
+];
+
+pub const MIN_MAP_WIDTH: i32 = 24;
+pub const MIN_MAP_HEIGHT: i32 = 16;
+/** 默认尺寸地图上的房间数 */
 const NUM_ROOMS: usize = 20;
+/** 默认尺寸地图上的怪物数 */
 const NUM_MONSTERS: usize = 50;
+/** 每只怪物至少对应的地板数，地板很少时相应减少怪物 */
+const FLOOR_PER_MONSTER: usize = 15;
+/** 怪物出生点与玩家起点之间的最小路径距离 */
+const MIN_SPAWN_DISTANCE: f32 = 10.0;
+/** 统计怪物密度的区域边长 */
+const REGION_SIZE: i32 = 10;
+/** 每个区域内出生点的上限，避免怪物扎堆 */
+const MAX_REGION_SPAWNS: usize = 3;
+/** 按`REGION_SIZE`划分的区域总数 */
+fn region_count(map: &Map) -> usize {
+    let regions_x = (map.width + REGION_SIZE - 1) / REGION_SIZE;
+    let regions_y = (map.height + REGION_SIZE - 1) / REGION_SIZE;
+    usize::try_from(regions_x * regions_y).unwrap_or(0)
+}
+
+/** 位置所在区域的编号 */
+fn region(map: &Map, pos: Point) -> usize {
+    let regions_x = (map.width + REGION_SIZE - 1) / REGION_SIZE;
+    usize::try_from(pos.y / REGION_SIZE * regions_x + pos.x / REGION_SIZE).unwrap_or(0)
+}
+
 pub struct MapBuilder {
     pub map: Map,
     pub rooms: Vec<Rect>,
     pub monster_spawns: Vec<Point>,
     pub player_start: Point,
     pub amulet_start: Point,
+    pub theme: Box<dyn MapTheme>,
+    /** 生成过程中各个阶段的地图，供`--visualize`回放，不回放时为空 */
+    pub history: Vec<Map>,
+    record_history: bool,
+    /** `MapBuilder::new`选中的构建链编号 */
+    pub method_seed: i32,
+    /** 从玩家起点出发的距离，地形不变时各个步骤共用 */
+    flow: FlowField,
 }
 
 impl MapBuilder {
-    /** 随机选择一个地图构建器，倾向于DrunkarksWalkArchitect，这个是联通的 */
-    pub fn new(rng: &mut RandomNumberGenerator) -> Self {
-        let method_seed = rng.range(0, 4);
-        let mut architect: Box<dyn MapArchitect> = match method_seed {
-            0 => Box::new(CellularAutomataArchitect {}),
-            1 => Box::new(RoomArchitect {}),
-            2 => Box::new(EmptyArchitect {}),
-            _ => Box::new(DrunkarksWalkArchitect {}),
+    /** 先选择主题，再从`depth`层可以选用的构建链中随机选择一条，构建链最后铺上主题的地形 */
+    pub fn new(
+        rng: &mut RandomNumberGenerator,
+        vaults: &Vaults,
+        width: i32,
+        height: i32,
+        depth: u32,
+        record_history: bool,
+    ) -> Self {
+        let theme = ThemeKind::random(rng);
+        //表格的每一行都不为空，取不到时用总是连通的醉汉漫步
+        let method_seed = *rng
+            .random_slice_entry(Self::level_chains(depth))
+            .unwrap_or(&10);
+        let chain = match method_seed {
+            0 => BuilderChain::standard(CellularAutomataArchitect {}, vaults),
+            1 => BuilderChain::rooms(vaults),
+            2 => BuilderChain::standard(EmptyArchitect {}, vaults),
+            3 => {
+                let style = CorridorStyle::random(rng);
+                BuilderChain::room_chain(RoomArchitect {}, NearestCorridors(style), vaults)
+            }
+            4 => {
+                let style = CorridorStyle::random(rng);
+                BuilderChain::room_chain(BspArchitect::new(), NearestCorridors(style), vaults)
+            }
+            5 => BuilderChain::room_chain(
+                BspInteriorArchitect {},
+                NearestCorridors(CorridorStyle::Dogleg),
+                vaults,
+            ),
+            6 => BuilderChain::standard(MazeArchitect {}, vaults),
+            7 => BuilderChain::standard(VoronoiArchitect {}, vaults),
+            8 => BuilderChain::standard(DlaArchitect(DlaStyle::random(rng)), vaults),
+            9 => {
+                let source = WfcSource::random(rng, vaults);
+                BuilderChain::standard(WfcArchitect::new(source), vaults)
+            }
+            _ => BuilderChain::standard(DrunkarksWalkArchitect {}, vaults),
         };
-        println!("method_seed: {}", method_seed);
-        architect.new(rng)
+        let chain = chain.with(TerrainPools(theme.terrain()));
+        let chain = if depth < DUNGEON_DEPTH {
+            chain.with(ExitStairs)
+        } else {
+            chain
+        };
+        let mut mb = chain.build(rng, width, height, record_history);
+        mb.method_seed = method_seed;
+        mb.theme = theme.theme();
+        mb
+    }
+
+    /** `depth`层可以选用的构建链，层数从1开始 */
+    fn level_chains(depth: u32) -> &'static [i32] {
+        let row = usize::try_from(depth.saturating_sub(1)).unwrap_or(usize::MAX);
+        LEVEL_CHAINS[row.min(LEVEL_CHAINS.len() - 1)]
+    }
+
+    /** 各个构建器共用的初始状态 */
+    fn empty(width: i32, height: i32) -> Self {
+        Self {
+            map: Map::new(width, height),
+            rooms: Vec::new(),
+            monster_spawns: Vec::new(),
+            player_start: Point::zero(),
+            amulet_start: Point::zero(),
+            theme: Box::new(DungeonTheme),
+            history: Vec::new(),
+            record_history: false,
+            method_seed: 0,
+            flow: FlowField::default(),
+        }
     }
+
+    /** 记录当前地图，构建器在每个值得观察的阶段调用，不回放时什么也不做 */
+    fn take_snapshot(&mut self) {
+        if self.record_history {
+            self.history.push(self.map.clone());
+        }
+    }
+
+    /** 把护身符的位置换成通往下一层的楼梯 */
+    pub fn place_exit(&mut self) {
+        let idx = self.map.point2d_to_index(self.amulet_start);
+        self.map.set_tile(idx, TileType::Exit);
+    }
+
     fn fill(&mut self, tile: TileType) {
         self.map.tiles.iter_mut().for_each(|t| *t = tile);
     }
 
-    fn find_most_distant(&self) -> Point {
-        let dijkstra_map = DijkstraMap::new(
-            SCREEN_WIDTH,
-            SCREEN_HEIGHT,
-            &vec![self.map.point2d_to_index(self.player_start)],
-            &self.map,
-            1024.0,
-        );
-        const UNREACHABLE: &f32 = &f32::MAX;
-        self.map.index_to_point2d(
-            dijkstra_map
-                .map
-                .iter()
-                .enumerate()
-                .filter(|(_, dist)| *dist < UNREACHABLE)
-                .max_by(|a, b| a.1.partial_cmp(b.1).unwrap())
-                .unwrap()
-                .0,
-        )
-    }
-    /**从地图上随机选择一个点作为怪物的出生点，如果采用元胞自动机，位置可能不可达*/
-    fn spawn_monster(&self, start: &Point, rng: &mut RandomNumberGenerator) -> Vec<Point> {
+    fn wall_border(&mut self) {
+        for y in 0..self.map.height {
+            for x in 0..self.map.width {
+                if self.map.is_border(Point::new(x, y)) {
+                    let idx = self.map.idx(x, y);
+                    self.map.tiles[idx] = TileType::Wall;
+                }
+            }
+        }
+    }
+
+    fn center(&self) -> Point {
+        Point::new(self.map.width / 2, self.map.height / 2)
+    }
+
+    /** 把默认尺寸（`MAP_WIDTH`×`MAP_HEIGHT`）地图上的数量按面积换算到当前地图，
+     * 房间、怪物、地形池等的数量都用它随地图大小缩放 */
+    fn scaled(&self, count: usize) -> usize {
+        count * self.map.tiles.len() / usize::try_from(MAP_WIDTH * MAP_HEIGHT).unwrap_or(1)
+    }
+
+    fn floor_count(&self) -> usize {
+        self.map
+            .tiles
+            .iter()
+            .filter(|t| **t == TileType::Floor)
+            .count()
+    }
+
+    /** 找到距离`target`最近的地板，没有地板时返回None */
+    fn nearest_floor(&self, target: Point) -> Option<Point> {
+        self.map
+            .tiles
+            .iter()
+            .enumerate()
+            .filter(|(_, t)| **t == TileType::Floor)
+            .map(|(idx, _)| self.map.index_to_point2d(idx))
+            .min_by(|a, b| {
+                let distance_a = DistanceAlg::Pythagoras.distance2d(target, *a);
+                let distance_b = DistanceAlg::Pythagoras.distance2d(target, *b);
+                distance_a.partial_cmp(&distance_b).unwrap()
+            })
+    }
+
+    /** 运行地形生成器，生成器声明了`min_floor_divisor`时反复生成，直到中心区域足够大，
+     * 避免玩家被困在一个小洞里 */
+    fn build_terrain(&mut self, rng: &mut RandomNumberGenerator, architect: &mut dyn MapArchitect) {
+        loop {
+            architect.build(rng, self);
+            match architect.min_floor_divisor() {
+                Some(divisor) if !self.keep_central_region(divisor) => {}
+                _ => return,
+            }
+        }
+    }
+
+    /** 只保留离中心最近的地板所在的区域，返回它的地板是否至少占地图的1/`min_floor_divisor` */
+    fn keep_central_region(&mut self, min_floor_divisor: usize) -> bool {
+        let Some(start) = self.nearest_floor(self.center()) else {
+            return false;
+        };
+        self.cull_unreachable(start);
+        self.floor_count() >= self.map.tiles.len() / min_floor_divisor
+    }
+
+    fn dijkstra_from(&mut self, start: Point) -> &DijkstraMap {
+        self.flow.get(&self.map, &[self.map.point2d_to_index(start)])
+    }
+
+    /** 把从`start`出发不可达的地块都设为墙，使地图成为一个连通图 */
+    fn cull_unreachable(&mut self, start: Point) {
+        //生成器挖完地形后马上调用这里，缓存的距离可能已经过期
+        self.map.tiles_changed();
+        let start_idx = self.map.point2d_to_index(start);
+        let unreachable: Vec<usize> = self
+            .dijkstra_from(start)
+            .map
+            .iter()
+            .enumerate()
+            //Dijkstra图不会把起点本身记为0，孤立的起点也要保留
+            .filter(|(idx, distance)| **distance >= f32::MAX && *idx != start_idx)
+            .map(|(idx, _)| idx)
+            .collect();
+        for idx in unreachable {
+            self.map.set_tile(idx, TileType::Wall);
+        }
+    }
+
+    /** 从玩家起点出发最远的可达地板 */
+    fn find_most_distant(&mut self) -> Point {
+        let dijkstra_map = self
+            .flow
+            .get(&self.map, &[self.map.point2d_to_index(self.player_start)]);
+        dijkstra_map
+            .map
+            .iter()
+            .enumerate()
+            .filter(|(idx, dist)| **dist < f32::MAX && self.map.tiles[*idx] == TileType::Floor)
+            .max_by(|a, b| a.1.partial_cmp(b.1).unwrap())
+            .map_or(self.player_start, |(idx, _)| self.map.index_to_point2d(idx))
+    }
+
+    /** 从起点可达、路径距离超过`MIN_SPAWN_DISTANCE`的地板中随机选择怪物的出生点，已有的出生点会保留。
+     * 总数按地图面积缩放，且不超过地板数除以`FLOOR_PER_MONSTER`，每个区域最多`MAX_REGION_SPAWNS`个 */
+    fn spawn_monsters(&mut self, rng: &mut RandomNumberGenerator) {
+        let dijkstra_map = self
+            .flow
+            .get(&self.map, &[self.map.point2d_to_index(self.player_start)]);
+        let mut region_spawns = vec![0; region_count(&self.map)];
+        for pos in &self.monster_spawns {
+            region_spawns[region(&self.map, *pos)] += 1;
+        }
         let mut spawnable_tiles: Vec<Point> = self
             .map
             .tiles
             .iter()
             .enumerate()
             .filter(|(idx, t)| {
-                **t == TileType::Floor
-                    && DistanceAlg::Pythagoras.distance2d(*start, self.map.index_to_point2d(*idx))
-                        > 10.0
+                let distance = dijkstra_map.map[*idx];
+                **t == TileType::Floor && distance < f32::MAX && distance > MIN_SPAWN_DISTANCE
             })
             .map(|(idx, _)| self.map.index_to_point2d(idx))
+            .filter(|pos| {
+                *pos != self.player_start
+                    && *pos != self.amulet_start
+                    && !self.monster_spawns.contains(pos)
+            })
             .collect();
-        let mut spawns = Vec::new();
-        for _ in 0..NUM_MONSTERS {
-            let target_idx = rng.random_slice_index(&spawnable_tiles).unwrap();
-            spawns.push(spawnable_tiles[target_idx].clone());
-            spawnable_tiles.remove(target_idx);
+        let desired = self
+            .scaled(NUM_MONSTERS)
+            .min(self.floor_count() / FLOOR_PER_MONSTER);
+        while self.monster_spawns.len() < desired {
+            let Some(target_idx) = rng.random_slice_index(&spawnable_tiles) else {
+                break;
+            };
+            let pos = spawnable_tiles.swap_remove(target_idx);
+            if region_spawns[region(&self.map, pos)] < MAX_REGION_SPAWNS {
+                region_spawns[region(&self.map, pos)] += 1;
+                self.monster_spawns.push(pos);
+            }
+        }
+    }
+
+    /** 去掉重复的、不在地板上的、从起点不可达的，以及与玩家或护身符重合的出生点。
+     * 房间和地窖放下的出生点也要遵守每个区域`MAX_REGION_SPAWNS`的上限，超出的按先后顺序去掉 */
+    fn prune_spawns(&mut self) {
+        let dijkstra_map = self
+            .flow
+            .get(&self.map, &[self.map.point2d_to_index(self.player_start)]);
+        let mut region_spawns = vec![0; region_count(&self.map)];
+        let mut seen = Vec::new();
+        let spawns = std::mem::take(&mut self.monster_spawns);
+        for pos in spawns {
+            let idx = self.map.point2d_to_index(pos);
+            let region = region(&self.map, pos);
+            let keep = self.map.tiles[idx] == TileType::Floor
+                && dijkstra_map.map[idx] < f32::MAX
+                && pos != self.player_start
+                && pos != self.amulet_start
+                && !seen.contains(&pos)
+                && region_spawns[region] < MAX_REGION_SPAWNS;
+            seen.push(pos);
+            if keep {
+                region_spawns[region] += 1;
+                self.monster_spawns.push(pos);
+            }
         }
-        spawns
     }
 
     fn build_random_rooms(&mut self, rng: &mut RandomNumberGenerator) {
+        let num_rooms = self.scaled(NUM_ROOMS).max(2);
         // (2)
-        while self.rooms.len() < NUM_ROOMS {
+        while self.rooms.len() < num_rooms {
             // (3)
             let room = Rect::with_size(
                 // (4)
-                rng.range(1, SCREEN_WIDTH - 10),
-                rng.range(1, SCREEN_HEIGHT - 10),
+                rng.range(1, self.map.width - 10),
+                rng.range(1, self.map.height - 10),
                 rng.range(2, 10),
                 rng.range(2, 10),
             );
             let mut overlap = false; // (5)
-            for r in self.rooms.iter() {
+            for r in &self.rooms {
                 if r.intersect(&room) {
                     overlap = true;
                 }
@@ -103,13 +458,14 @@ impl MapBuilder {
             if !overlap {
                 // (6)
                 room.for_each(|p| {
-                    if p.x > 0 && p.x < SCREEN_WIDTH && p.y > 0 && p.y < SCREEN_HEIGHT {
-                        let idx = map_idx(p.x, p.y);
+                    if !self.map.is_border(p) {
+                        let idx = self.map.idx(p.x, p.y);
                         self.map.tiles[idx] = TileType::Floor;
                     }
                 });
 
-                self.rooms.push(room)
+                self.rooms.push(room);
+                self.take_snapshot();
             }
         }
     }
@@ -118,7 +474,7 @@ impl MapBuilder {
         use std::cmp::{max, min};
         for x in min(x1, x2)..=max(x1, x2) {
             if let Some(idx) = self.map.try_idx(Point::new(x, y)) {
-                self.map.tiles[idx as usize] = TileType::Floor;
+                self.map.set_tile(idx, TileType::Floor);
             }
         }
     }
@@ -127,27 +483,7 @@ impl MapBuilder {
         use std::cmp::{max, min};
         for y in min(y1, y2)..=max(y1, y2) {
             if let Some(idx) = self.map.try_idx(Point::new(x, y)) {
-                self.map.tiles[idx as usize] = TileType::Floor;
-            }
-        }
-    }
-
-    fn build_corridors(&mut self, rng: &mut RandomNumberGenerator) {
-        let mut rooms = self.rooms.clone();
-        rooms.sort_by(|a, b| a.center().x.cmp(&b.center().x)); // (7)
-
-        for (i, room) in rooms.iter().enumerate().skip(1) {
-            // (8)
-            let prev = rooms[i - 1].center(); // (9)
-            let new = room.center();
-
-            if rng.range(0, 2) == 1 {
-                // (10)
-                self.apply_horizontal_tunnel(prev.x, new.x, prev.y);
-                self.apply_vertical_tunnel(prev.y, new.y, new.x);
-            } else {
-                self.apply_vertical_tunnel(prev.y, new.y, prev.x);
-                self.apply_horizontal_tunnel(prev.x, new.x, new.y);
+                self.map.set_tile(idx, TileType::Floor);
             }
         }
     }
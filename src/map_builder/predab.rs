@@ -1,5 +1,18 @@
+use super::MetaMapBuilder;
 use crate::prelude::*;
+use std::fmt;
+use std::fs;
 
+/** 额外地窖所在的目录，每个`.txt`文件描述一个地窖 */
+pub const VAULT_DIR: &str = "resources/vaults";
+/** 手绘的WFC样本所在的目录，格式与地窖相同 */
+pub const SAMPLE_DIR: &str = "resources/wfc";
+/** 地窖与玩家起点之间的最小路径距离 */
+const MIN_VAULT_DISTANCE: f32 = 20.0;
+/** 寻找放置位置的最大尝试次数，找不到时这一层就不放地窖 */
+const MAX_VAULT_ATTEMPTS: usize = 20;
+
+/** 内置地窖：`#`为墙，`-`为地板，`M`为怪物出生点 */
 const FORTRESS: (&str, i32, i32) = (
     "
     ------------
@@ -17,3 +30,238 @@ const FORTRESS: (&str, i32, i32) = (
     12,
     11,
 );
+
+#[derive(Clone)]
+pub struct Vault {
+    name: String,
+    width: i32,
+    height: i32,
+    cells: Vec<char>,
+}
+
+/** 所有手绘的地图片段：可用的地窖和WFC样本，启动时读取一次 */
+#[derive(Clone)]
+pub struct Vaults {
+    vaults: Vec<Vault>,
+    samples: Vec<Vault>,
+}
+
+#[derive(Debug)]
+pub enum VaultError {
+    Io(&'static str, std::io::Error),
+    Invalid { name: String, reason: String },
+}
+
+impl fmt::Display for VaultError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            VaultError::Io(dir, e) => write!(f, "无法读取目录 {dir}: {e}"),
+            VaultError::Invalid { name, reason } => write!(f, "地窖\"{name}\"无效: {reason}"),
+        }
+    }
+}
+
+impl std::error::Error for VaultError {}
+
+impl Vault {
+    /** 每行去掉首尾空白后就是地图的一行，空行会被忽略，所有行必须等长 */
+    pub(super) fn parse(name: &str, text: &str) -> Result<Self, VaultError> {
+        let invalid = |reason: &str| VaultError::Invalid {
+            name: name.to_string(),
+            reason: reason.to_string(),
+        };
+        let rows: Vec<&str> = text
+            .lines()
+            .map(str::trim)
+            .filter(|row| !row.is_empty())
+            .collect();
+        let width = rows.first().map_or(0, |row| row.chars().count());
+        if width == 0 {
+            return Err(invalid("地窖不能为空"));
+        }
+        if rows.iter().any(|row| row.chars().count() != width) {
+            return Err(invalid("每一行的长度必须相同"));
+        }
+        let cells: Vec<char> = rows.iter().flat_map(|row| row.chars()).collect();
+        if cells.iter().any(|c| !matches!(c, '#' | '-' | 'M')) {
+            return Err(invalid("只能包含'#'、'-'和'M'"));
+        }
+        let (Ok(width), Ok(height)) = (i32::try_from(width), i32::try_from(rows.len())) else {
+            return Err(invalid("地窖太大"));
+        };
+        Ok(Self {
+            name: name.to_string(),
+            width,
+            height,
+            cells,
+        })
+    }
+
+    fn cell(&self, x: i32, y: i32) -> char {
+        self.cells[usize::try_from(y * self.width + x).unwrap_or(0)]
+    }
+
+    pub fn width(&self) -> i32 {
+        self.width
+    }
+
+    pub fn height(&self) -> i32 {
+        self.height
+    }
+
+    /** `#`是墙，其余都是地板 */
+    pub fn tile(&self, x: i32, y: i32) -> TileType {
+        if self.cell(x, y) == '#' {
+            TileType::Wall
+        } else {
+            TileType::Floor
+        }
+    }
+}
+
+impl Vaults {
+    /** 内置的FORTRESS加上`VAULT_DIR`中的所有地窖，以及`SAMPLE_DIR`中的WFC样本，目录不存在时跳过 */
+    pub fn load() -> Result<Self, VaultError> {
+        let (text, width, height) = FORTRESS;
+        let fortress = Vault::parse("fortress", text)?;
+        if fortress.width != width || fortress.height != height {
+            return Err(VaultError::Invalid {
+                name: fortress.name,
+                reason: "尺寸与声明不一致".to_string(),
+            });
+        }
+        let mut vaults = vec![fortress];
+        vaults.extend(Self::load_dir(VAULT_DIR)?);
+        let samples = Self::load_dir(SAMPLE_DIR)?;
+        Ok(Self { vaults, samples })
+    }
+
+    pub fn samples(&self) -> &[Vault] {
+        &self.samples
+    }
+
+    fn load_dir(dir: &'static str) -> Result<Vec<Vault>, VaultError> {
+        let entries = match fs::read_dir(dir) {
+            Ok(entries) => entries,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(e) => return Err(VaultError::Io(dir, e)),
+        };
+        let mut paths = Vec::new();
+        for entry in entries {
+            let path = entry.map_err(|e| VaultError::Io(dir, e))?.path();
+            if path.extension().is_some_and(|ext| ext == "txt") {
+                paths.push(path);
+            }
+        }
+        //按文件名排序，保证同一个种子生成同样的地图
+        paths.sort();
+        let mut vaults = Vec::new();
+        for path in paths {
+            let name = path
+                .file_stem()
+                .map_or_else(String::new, |stem| stem.to_string_lossy().into_owned());
+            let text = fs::read_to_string(&path).map_err(|e| VaultError::Io(dir, e))?;
+            vaults.push(Vault::parse(&name, &text)?);
+        }
+        Ok(vaults)
+    }
+}
+
+/** 构建链步骤：随机选一个地窖，盖在离玩家足够远且可达的位置，地窖内原有的怪物出生点换成地窖的`M` */
+pub struct ApplyVault {
+    vaults: Vaults,
+}
+
+impl ApplyVault {
+    pub fn new(vaults: &Vaults) -> Self {
+        Self {
+            vaults: vaults.clone(),
+        }
+    }
+}
+
+impl MetaMapBuilder for ApplyVault {
+    fn build(&mut self, rng: &mut RandomNumberGenerator, mb: &mut MapBuilder) {
+        let Some(vault) = rng.random_slice_entry(&self.vaults.vaults) else {
+            return;
+        };
+        //地图太小放不下这个地窖
+        if vault.width > mb.map.width - 4 || vault.height > mb.map.height - 4 {
+            return;
+        }
+        //盖地窖会改变地形，先留一份放置前的距离
+        let before = mb.dijkstra_from(mb.player_start).map.clone();
+        for _ in 0..MAX_VAULT_ATTEMPTS {
+            //不碰最外圈的墙
+            let area = Rect::with_size(
+                rng.range(1, mb.map.width - vault.width - 1),
+                rng.range(1, mb.map.height - vault.height - 1),
+                vault.width,
+                vault.height,
+            );
+            let mut reachable = false;
+            let mut too_close = false;
+            area.for_each(|pt| {
+                let distance = before[mb.map.point2d_to_index(pt)];
+                reachable |= distance < f32::MAX;
+                too_close |= distance < MIN_VAULT_DISTANCE;
+            });
+            if !reachable
+                || too_close
+                || area.point_in_rect(mb.player_start)
+                || area.point_in_rect(mb.amulet_start)
+            {
+                continue;
+            }
+            if Self::stamp_vault(mb, vault, area, &before) {
+                return;
+            }
+        }
+    }
+}
+
+impl ApplyVault {
+    /** 盖上地窖后，原本可达的地块或地窖里的怪物变得不可达时撤销这次放置 */
+    pub(super) fn stamp_vault(mb: &mut MapBuilder, vault: &Vault, area: Rect, before: &[f32]) -> bool {
+        let old_tiles = mb.map.tiles.clone();
+        let mut spawns: Vec<Point> = mb
+            .monster_spawns
+            .iter()
+            .filter(|pos| !area.point_in_rect(**pos))
+            .copied()
+            .collect();
+        for y in 0..vault.height {
+            for x in 0..vault.width {
+                let pos = Point::new(area.x1 + x, area.y1 + y);
+                let idx = mb.map.point2d_to_index(pos);
+                let tile = match vault.cell(x, y) {
+                    '#' => TileType::Wall,
+                    'M' => {
+                        spawns.push(pos);
+                        TileType::Floor
+                    }
+                    _ => TileType::Floor,
+                };
+                mb.map.set_tile(idx, tile);
+            }
+        }
+
+        let after = mb
+            .flow
+            .get(&mb.map, &[mb.map.point2d_to_index(mb.player_start)]);
+        let is_reachable = |pos: &Point| after.map[mb.map.point2d_to_index(*pos)] < f32::MAX;
+        let cut_off = before.iter().enumerate().any(|(idx, distance)| {
+            *distance < f32::MAX
+                && after.map[idx] >= f32::MAX
+                && !area.point_in_rect(mb.map.index_to_point2d(idx))
+        });
+        if !cut_off && spawns.iter().all(is_reachable) {
+            mb.monster_spawns = spawns;
+            true
+        } else {
+            mb.map.tiles = old_tiles;
+            mb.map.tiles_changed();
+            false
+        }
+    }
+}
@@ -0,0 +1,539 @@
+use super::predab::Vault;
+use super::*;
+
+/** 每个构建器测试的种子数量 */
+const SEEDS: u64 = 100;
+
+/** 大地图和最小尺寸地图上测试的种子数量，大地图生成较慢 */
+const SIZED_SEEDS: u64 = 5;
+
+/** 用指定种子运行整条构建链，并检查所有构建器都应满足的不变量 */
+fn check_chain(name: &str, make: fn(&Vaults) -> BuilderChain) {
+    let vaults = Vaults::load().expect("vaults should load");
+    for seed in 0..SEEDS {
+        let mut rng = RandomNumberGenerator::seeded(seed);
+        let mb = make(&vaults).build(&mut rng, MAP_WIDTH, MAP_HEIGHT, false);
+        check_invariants(&mb, &format!("{name} seed {seed}"));
+    }
+    for (width, height) in [(200, 120), (MIN_MAP_WIDTH, MIN_MAP_HEIGHT)] {
+        for seed in 0..SIZED_SEEDS {
+            let mut rng = RandomNumberGenerator::seeded(seed);
+            let mb = make(&vaults).build(&mut rng, width, height, false);
+            let context = format!("{name} {width}x{height} seed {seed}");
+            assert_eq!((mb.map.width, mb.map.height), (width, height), "{context}");
+            check_invariants(&mb, &context);
+        }
+    }
+}
+
+fn check_invariants(mb: &MapBuilder, context: &str) {
+    let map = &mb.map;
+    assert_eq!(
+        map.tiles[map.point2d_to_index(mb.player_start)],
+        TileType::Floor,
+        "{context}: player_start {:?} is not a floor",
+        mb.player_start
+    );
+    //下一层的楼梯就放在护身符的位置上
+    assert!(
+        matches!(
+            map.tiles[map.point2d_to_index(mb.amulet_start)],
+            TileType::Floor | TileType::Exit
+        ),
+        "{context}: amulet_start {:?} is neither a floor nor the exit",
+        mb.amulet_start
+    );
+
+    for x in 0..map.width {
+        for y in [0, map.height - 1] {
+            assert_eq!(
+                map.tiles[map.idx(x, y)],
+                TileType::Wall,
+                "{context}: border ({x}, {y}) is not a wall"
+            );
+        }
+    }
+    for y in 0..map.height {
+        for x in [0, map.width - 1] {
+            assert_eq!(
+                map.tiles[map.idx(x, y)],
+                TileType::Wall,
+                "{context}: border ({x}, {y}) is not a wall"
+            );
+        }
+    }
+
+    let dijkstra_map = DijkstraMap::new(
+        map.width,
+        map.height,
+        &[map.point2d_to_index(mb.player_start)],
+        map,
+        map.max_path_depth(),
+    );
+    for (i, spawn) in mb.monster_spawns.iter().enumerate() {
+        assert_ne!(
+            *spawn, mb.player_start,
+            "{context}: monster spawns on the player"
+        );
+        assert_ne!(
+            *spawn, mb.amulet_start,
+            "{context}: monster spawns on the amulet"
+        );
+        assert_eq!(
+            map.tiles[map.point2d_to_index(*spawn)],
+            TileType::Floor,
+            "{context}: monster spawn {spawn:?} is not a floor"
+        );
+        assert!(
+            !mb.monster_spawns[..i].contains(spawn),
+            "{context}: duplicate monster spawn {spawn:?}"
+        );
+        assert!(
+            dijkstra_map.map[map.point2d_to_index(*spawn)] < f32::MAX,
+            "{context}: monster spawn {spawn:?} is unreachable from the player"
+        );
+    }
+}
+
+/** 每一层随机选择构建链和主题后不变量依然成立，选中的构建链是这一层允许的 */
+#[test]
+fn map_builder_invariants() {
+    let vaults = Vaults::load().expect("vaults should load");
+    for depth in 1..=DUNGEON_DEPTH + 1 {
+        let allowed = MapBuilder::level_chains(depth);
+        for seed in 0..SEEDS {
+            let mut rng = RandomNumberGenerator::seeded(seed);
+            let mb = MapBuilder::new(&mut rng, &vaults, MAP_WIDTH, MAP_HEIGHT, depth, false);
+            let context = format!("depth {depth} seed {seed}");
+            assert!(
+                allowed.contains(&mb.method_seed),
+                "{context}: chain {} is not allowed",
+                mb.method_seed
+            );
+            check_invariants(&mb, &context);
+            let exits = mb
+                .map
+                .tiles
+                .iter()
+                .filter(|t| **t == TileType::Exit)
+                .count();
+            if depth < DUNGEON_DEPTH {
+                assert_eq!(exits, 1, "{context}: expected one exit");
+                assert_eq!(
+                    mb.map.tiles[mb.map.point2d_to_index(mb.amulet_start)],
+                    TileType::Exit,
+                    "{context}: the exit is not at amulet_start"
+                );
+            } else {
+                assert_eq!(exits, 0, "{context}: the deepest level has no exit");
+            }
+        }
+    }
+    //每条构建链至少在一层中用到
+    for method in 0..11 {
+        assert!(
+            LEVEL_CHAINS.iter().any(|chains| chains.contains(&method)),
+            "chain {method} is never used"
+        );
+    }
+}
+
+#[test]
+fn room_architect_invariants() {
+    check_chain("rooms", BuilderChain::rooms);
+}
+
+#[test]
+fn cellular_automata_architect_invariants() {
+    check_chain("cellular automata", |vaults| {
+        BuilderChain::standard(CellularAutomataArchitect {}, vaults)
+    });
+}
+
+#[test]
+fn drunkards_walk_architect_invariants() {
+    check_chain("drunkard's walk", |vaults| {
+        BuilderChain::standard(DrunkarksWalkArchitect {}, vaults)
+    });
+}
+
+#[test]
+fn empty_architect_invariants() {
+    check_chain("empty", |vaults| BuilderChain::standard(EmptyArchitect {}, vaults));
+}
+
+#[test]
+fn nearest_corridor_invariants() {
+    check_chain("nearest dogleg", |vaults| {
+        BuilderChain::room_chain(
+            RoomArchitect {},
+            NearestCorridors(CorridorStyle::Dogleg),
+            vaults,
+        )
+    });
+    check_chain("nearest straight", |vaults| {
+        BuilderChain::room_chain(
+            RoomArchitect {},
+            NearestCorridors(CorridorStyle::Straight),
+            vaults,
+        )
+    });
+}
+
+#[test]
+fn bsp_architect_invariants() {
+    check_chain("bsp", |vaults| {
+        BuilderChain::room_chain(
+            BspArchitect::new(),
+            NearestCorridors(CorridorStyle::Straight),
+            vaults,
+        )
+    });
+}
+
+#[test]
+fn bsp_interior_architect_invariants() {
+    check_chain("bsp interior", |vaults| {
+        BuilderChain::room_chain(
+            BspInteriorArchitect {},
+            NearestCorridors(CorridorStyle::Dogleg),
+            vaults,
+        )
+    });
+}
+
+#[test]
+fn maze_architect_invariants() {
+    check_chain("maze", |vaults| BuilderChain::standard(MazeArchitect {}, vaults));
+}
+
+#[test]
+fn voronoi_architect_invariants() {
+    check_chain("voronoi", |vaults| BuilderChain::standard(VoronoiArchitect {}, vaults));
+}
+
+#[test]
+fn dla_architect_invariants() {
+    check_chain("dla walk inwards", |vaults| {
+        BuilderChain::standard(DlaArchitect(DlaStyle::WalkInwards), vaults)
+    });
+    check_chain("dla central attractor", |vaults| {
+        BuilderChain::standard(DlaArchitect(DlaStyle::CentralAttractor), vaults)
+    });
+}
+
+#[test]
+fn wfc_architect_invariants() {
+    check_chain("wfc from automata", |vaults| {
+        let source = WfcSource::Architect(Box::new(CellularAutomataArchitect {}));
+        BuilderChain::standard(WfcArchitect::new(source), vaults)
+    });
+    check_chain("wfc from sample", |vaults| {
+        let sample = vaults.samples().first().expect("wfc sample should load");
+        let source = WfcSource::Sample(sample.clone());
+        BuilderChain::standard(WfcArchitect::new(source), vaults)
+    });
+}
+
+/** 导出的文本地图读回后与原来完全一致 */
+#[test]
+fn ascii_round_trip() {
+    let vaults = Vaults::load().expect("vaults should load");
+    for seed in 0..SEEDS {
+        let mut rng = RandomNumberGenerator::seeded(seed);
+        let mb = MapBuilder::new(&mut rng, &vaults, MAP_WIDTH, MAP_HEIGHT, 1, false);
+        let loaded = MapBuilder::from_ascii(&mb.to_ascii()).expect("exported map should load");
+        let context = format!("seed {seed}");
+        assert_eq!(loaded.map.tiles, mb.map.tiles, "{context}");
+        assert_eq!(loaded.player_start, mb.player_start, "{context}");
+        let mut spawns = mb.monster_spawns.clone();
+        spawns.sort_by_key(|pos| (pos.y, pos.x));
+        assert_eq!(loaded.monster_spawns, spawns, "{context}");
+        if mb.amulet_start != mb.player_start {
+            assert_eq!(loaded.amulet_start, mb.amulet_start, "{context}");
+        }
+        check_invariants(&loaded, &context);
+    }
+}
+
+#[test]
+fn ascii_rejects_invalid_maps() {
+    let (width, height) = (
+        usize::try_from(MIN_MAP_WIDTH).unwrap(),
+        usize::try_from(MIN_MAP_HEIGHT).unwrap(),
+    );
+    //四周是墙、玩家在左上角的最小地图，`edit`可以改动其中的字符
+    let level = |edit: &dyn Fn(&mut Vec<Vec<char>>)| {
+        let mut grid: Vec<Vec<char>> = (0..height)
+            .map(|y| {
+                (0..width)
+                    .map(|x| {
+                        if x == 0 || y == 0 || x == width - 1 || y == height - 1 {
+                            '#'
+                        } else {
+                            '-'
+                        }
+                    })
+                    .collect()
+            })
+            .collect();
+        grid[1][1] = '@';
+        edit(&mut grid);
+        grid.iter()
+            .map(|row| row.iter().collect::<String>())
+            .collect::<Vec<_>>()
+            .join("\n")
+    };
+    let load = |edit: &dyn Fn(&mut Vec<Vec<char>>)| MapBuilder::from_ascii(&level(edit));
+
+    let valid = load(&|_| {}).expect("walled map should load");
+    check_invariants(&valid, "walled map");
+    assert!(load(&|g| g[1][1] = '-').is_err(), "missing player");
+    assert!(load(&|g| g[1][2] = '@').is_err(), "two players");
+    assert!(load(&|g| g[1][2] = 'x').is_err(), "unknown char");
+    assert!(load(&|g| g[1].truncate(3)).is_err(), "ragged rows");
+    assert!(load(&|g| g.truncate(3)).is_err(), "too small");
+    assert!(
+        load(&|g| g.iter_mut().flatten().for_each(|c| {
+            if *c == '#' {
+                *c = '-';
+            }
+        }))
+        .is_err(),
+        "all-floor map without border walls"
+    );
+    assert!(load(&|g| g[0][5] = '-').is_err(), "open top border");
+    assert!(load(&|g| g[5][width - 1] = '>').is_err(), "exit in the border");
+
+    //一道墙把地图分成左右两半，右半边的`A`和`M`从`@`走不到
+    let split = |g: &mut Vec<Vec<char>>| g.iter_mut().for_each(|row| row[width / 2] = '#');
+    assert!(load(&|g| split(g)).is_ok());
+    assert!(
+        load(&|g| {
+            split(g);
+            g[2][width - 2] = 'A';
+        })
+        .is_err(),
+        "unreachable amulet"
+    );
+    assert!(
+        load(&|g| {
+            split(g);
+            g[2][width - 2] = 'M';
+        })
+        .is_err(),
+        "unreachable monster spawn"
+    );
+}
+
+/** 四周是墙、内部填满`fill`的文本地图，`rows`给出的行从第1列开始覆盖 */
+fn fixed_map(width: usize, height: usize, fill: char, rows: &[(usize, &str)]) -> MapBuilder {
+    let mut grid: Vec<Vec<char>> = (0..height)
+        .map(|y| {
+            (0..width)
+                .map(|x| {
+                    if x == 0 || y == 0 || x == width - 1 || y == height - 1 {
+                        '#'
+                    } else {
+                        fill
+                    }
+                })
+                .collect()
+        })
+        .collect();
+    for (y, row) in rows {
+        for (x, c) in row.chars().enumerate() {
+            grid[*y][x + 1] = c;
+        }
+    }
+    let text: Vec<String> = grid.iter().map(|row| row.iter().collect()).collect();
+    MapBuilder::from_ascii(&text.join("\n")).expect("fixed map should load")
+}
+
+fn stamp(mb: &mut MapBuilder, vault: &str, area: Rect) -> bool {
+    let vault = Vault::parse("test", vault).expect("vault should parse");
+    let before = mb.dijkstra_from(mb.player_start).map.clone();
+    ApplyVault::stamp_vault(mb, &vault, area, &before)
+}
+
+#[test]
+fn vault_is_stamped_with_its_spawns() {
+    let mut mb = fixed_map(
+        30,
+        16,
+        '-',
+        &[(1, "@"), (7, "------------------M"), (12, "--M")],
+    );
+    let vault = "
+        -----
+        -#-#-
+        --M--
+        -###-
+        -----
+    ";
+    assert!(stamp(&mut mb, vault, Rect::with_size(18, 6, 5, 5)));
+    for (pos, tile) in [
+        (Point::new(19, 7), TileType::Wall),
+        (Point::new(21, 7), TileType::Wall),
+        (Point::new(20, 7), TileType::Floor),
+        (Point::new(19, 9), TileType::Wall),
+        (Point::new(20, 9), TileType::Wall),
+        (Point::new(21, 9), TileType::Wall),
+    ] {
+        assert_eq!(mb.map.tiles[mb.map.point2d_to_index(pos)], tile, "{pos:?}");
+    }
+    //地窖里原有的出生点被地窖的`M`取代，外面的出生点保留
+    assert!(mb.monster_spawns.contains(&Point::new(20, 8)));
+    assert!(!mb.monster_spawns.contains(&Point::new(19, 7)));
+    assert!(mb.monster_spawns.contains(&Point::new(3, 12)));
+    assert_eq!(mb.monster_spawns.len(), 2);
+}
+
+#[test]
+fn vault_that_cuts_off_the_map_is_undone() {
+    //只有一条走廊的地图，把走廊整个堵住
+    let mut mb = fixed_map(30, 16, '#', &[(8, &format!("@{}M", "-".repeat(25)))]);
+    let tiles = mb.map.tiles.clone();
+    let spawns = mb.monster_spawns.clone();
+    assert!(!stamp(&mut mb, "###\n###\n###", Rect::with_size(12, 7, 3, 3)));
+    assert_eq!(mb.map.tiles, tiles);
+    assert_eq!(mb.monster_spawns, spawns);
+
+    //地图本身还连通，但地窖里的`M`被围住了
+    let mut mb = fixed_map(30, 16, '-', &[(1, "@")]);
+    let tiles = mb.map.tiles.clone();
+    assert!(!stamp(&mut mb, "###\n#M#\n###", Rect::with_size(15, 6, 3, 3)));
+    assert_eq!(mb.map.tiles, tiles);
+    assert!(mb.monster_spawns.is_empty());
+}
+
+/** 在足够大的空地图上一定能放下一个地窖，地窖的`M`成为出生点 */
+#[test]
+fn apply_vault_places_a_vault() {
+    let vaults = Vaults::load().expect("vaults should load");
+    for seed in 0..SEEDS {
+        let mut mb = fixed_map(80, 50, '-', &[(1, "@")]);
+        let mut rng = RandomNumberGenerator::seeded(seed);
+        ApplyVault::new(&vaults).build(&mut rng, &mut mb);
+        let context = format!("seed {seed}");
+        let interior_walls = mb
+            .map
+            .tiles
+            .iter()
+            .enumerate()
+            .filter(|(idx, t)| {
+                **t == TileType::Wall && !mb.map.is_border(mb.map.index_to_point2d(*idx))
+            })
+            .count();
+        assert!(interior_walls > 0, "{context}: no vault was stamped");
+        assert!(!mb.monster_spawns.is_empty(), "{context}: vault has no spawns");
+        check_invariants(&mb, &context);
+    }
+}
+
+/** 地形池铺在地板上，不碰玩家、护身符和出生点；岩浆不会挡住原本可达的地方 */
+#[test]
+fn terrain_pools() {
+    //左边是开阔的房间，一条走廊通往右边的房间
+    let mut rows = vec![(1, "@".to_string())];
+    for y in 2..14 {
+        rows.push((y, format!("{}{}", "-".repeat(12), "#".repeat(5))));
+    }
+    rows.push((7, format!("{}{}M", "-".repeat(12), "-".repeat(5))));
+    let rows: Vec<(usize, &str)> = rows.iter().map(|(y, row)| (*y, row.as_str())).collect();
+    for tile in [TileType::Water, TileType::Lava, TileType::Rubble] {
+        let mut placed = 0;
+        for seed in 0..SEEDS {
+            let mut mb = fixed_map(30, 16, '-', &rows);
+            let original = mb.map.tiles.clone();
+            let mut rng = RandomNumberGenerator::seeded(seed);
+            TerrainPools(tile).build(&mut rng, &mut mb);
+            let context = format!("{tile:?} seed {seed}");
+            placed += mb.map.tiles.iter().filter(|t| **t == tile).count();
+            for (idx, (before, after)) in original.iter().zip(&mb.map.tiles).enumerate() {
+                assert!(
+                    before == after || (*before == TileType::Floor && *after == tile),
+                    "{context}: {:?} changed from {before:?} to {after:?}",
+                    mb.map.index_to_point2d(idx)
+                );
+            }
+            check_invariants(&mb, &context);
+
+            //把岩浆当成墙，房间和走廊依然连通
+            let mut safe = mb.map.clone();
+            safe.tiles
+                .iter_mut()
+                .filter(|t| t.damage() > 0)
+                .for_each(|t| *t = TileType::Wall);
+            let start = [safe.point2d_to_index(mb.player_start)];
+            let distances =
+                DijkstraMap::new(safe.width, safe.height, &start, &safe, safe.max_path_depth());
+            for (idx, t) in safe.tiles.iter().enumerate() {
+                assert!(
+                    !t.walkable() || distances.map[idx] < f32::MAX || idx == start[0],
+                    "{context}: {:?} is cut off by lava",
+                    safe.index_to_point2d(idx)
+                );
+            }
+        }
+        //会挡路的岩浆池被放弃，但不会每次都被放弃
+        assert!(placed > 0, "{tile:?}: no pools");
+    }
+}
+
+/** 每个区域内的出生点不超过`MAX_REGION_SPAWNS`个 */
+fn check_region_spawns(mb: &MapBuilder, context: &str) {
+    let mut region_spawns = vec![0; region_count(&mb.map)];
+    for pos in &mb.monster_spawns {
+        region_spawns[region(&mb.map, *pos)] += 1;
+    }
+    assert!(
+        region_spawns.iter().all(|count| *count <= MAX_REGION_SPAWNS),
+        "{context}: too many spawns in one region: {region_spawns:?}"
+    );
+}
+
+/** 房间中心和地窖放下的出生点同样受区域上限约束 */
+#[test]
+fn spawns_limited_per_region() {
+    let vaults = Vaults::load().expect("vaults should load");
+    for seed in 0..SEEDS {
+        let mut rng = RandomNumberGenerator::seeded(seed);
+        let mb = BuilderChain::rooms(&vaults).build(&mut rng, MAP_WIDTH, MAP_HEIGHT, false);
+        check_region_spawns(&mb, &format!("rooms seed {seed}"));
+    }
+
+    //同一个区域里挤满出生点，清理后只留下前面的几个
+    let mut mb = fixed_map(30, 16, '-', &[(1, "@")]);
+    let crowd: Vec<Point> = (11..19).map(|x| Point::new(x, 2)).collect();
+    mb.monster_spawns.clone_from(&crowd);
+    let mut rng = RandomNumberGenerator::seeded(0);
+    PruneSpawns.build(&mut rng, &mut mb);
+    assert_eq!(mb.monster_spawns, crowd[..MAX_REGION_SPAWNS]);
+    check_region_spawns(&mb, "crowded region");
+}
+
+/** 只有回放时才记录快照，同一个种子生成的地图不受影响 */
+#[test]
+fn history_only_recorded_when_visualizing() {
+    let vaults = Vaults::load().expect("vaults should load");
+    for seed in 0..SIZED_SEEDS {
+        let mut rng = RandomNumberGenerator::seeded(seed);
+        let quiet = MapBuilder::new(&mut rng, &vaults, MAP_WIDTH, MAP_HEIGHT, 1, false);
+        let mut rng = RandomNumberGenerator::seeded(seed);
+        let recorded = MapBuilder::new(&mut rng, &vaults, MAP_WIDTH, MAP_HEIGHT, 1, true);
+        let context = format!("seed {seed}");
+        assert!(quiet.history.is_empty(), "{context}");
+        assert!(!recorded.history.is_empty(), "{context}");
+        assert_eq!(
+            recorded.history.last().map(|map| &map.tiles),
+            Some(&recorded.map.tiles),
+            "{context}: the last snapshot is the finished map"
+        );
+        assert_eq!(quiet.map.tiles, recorded.map.tiles, "{context}");
+        //楼梯在导出和回放之前就已经放好
+        let exit = recorded.map.point2d_to_index(recorded.amulet_start);
+        assert_eq!(recorded.map.tiles[exit], TileType::Exit, "{context}");
+        assert!(recorded.to_ascii().contains('>'), "{context}");
+    }
+}
@@ -0,0 +1,157 @@
+use crate::prelude::*;
+use serde::{Deserialize, Serialize};
+
+/** 决定地块如何绘制，同一个构建器生成的地图换个主题就能呈现不同的外观 */
+pub trait MapTheme: Sync + Send {
+    /** 主题的种类，存档时只保存种类 */
+    fn kind(&self) -> ThemeKind;
+    fn tile_to_render(&self, tile_type: TileType) -> FontCharType;
+    /** 地块在视野内的着色 */
+    fn tile_color(&self, tile_type: TileType) -> RGB;
+    /** 已探索但不在视野内的地块显示为灰暗的颜色 */
+    fn remembered_color(&self, tile_type: TileType) -> RGB {
+        self.tile_color(tile_type)
+            .to_greyscale()
+            .lerp(RGB::named(BLACK), 0.34)
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub enum ThemeKind {
+    Dungeon,
+    Forest,
+    Cavern,
+    Lava,
+}
+
+impl ThemeKind {
+    pub fn random(rng: &mut RandomNumberGenerator) -> Self {
+        match rng.range(0, 4) {
+            0 => ThemeKind::Dungeon,
+            1 => ThemeKind::Forest,
+            2 => ThemeKind::Cavern,
+            _ => ThemeKind::Lava,
+        }
+    }
+
+    /** 构建链最后由`TerrainPools`铺在地板上的地形 */
+    pub fn terrain(self) -> TileType {
+        match self {
+            ThemeKind::Dungeon => TileType::Rubble,
+            ThemeKind::Forest | ThemeKind::Cavern => TileType::Water,
+            ThemeKind::Lava => TileType::Lava,
+        }
+    }
+
+    pub fn theme(self) -> Box<dyn MapTheme> {
+        match self {
+            ThemeKind::Dungeon => Box::new(DungeonTheme),
+            ThemeKind::Forest => Box::new(ForestTheme),
+            ThemeKind::Cavern => Box::new(CavernTheme),
+            ThemeKind::Lava => Box::new(LavaTheme),
+        }
+    }
+}
+
+/** 字体中没有门、水和岩浆的图块，各主题共用现有图块并靠着色区分 */
+fn terrain_glyph(tile_type: TileType) -> FontCharType {
+    match tile_type {
+        TileType::Wall | TileType::DoorClosed => to_cp437('#'),
+        TileType::Floor | TileType::DoorOpen | TileType::Water | TileType::Lava => to_cp437('.'),
+        TileType::Rubble => to_cp437(';'),
+        TileType::Exit => to_cp437('>'),
+    }
+}
+
+fn terrain_color(tile_type: TileType) -> RGB {
+    match tile_type {
+        TileType::DoorClosed | TileType::DoorOpen => RGB::named(SADDLE_BROWN),
+        TileType::Water => RGB::named(DODGER_BLUE),
+        TileType::Lava => RGB::named(RED),
+        TileType::Rubble => RGB::named(GRAY),
+        TileType::Wall | TileType::Floor | TileType::Exit => RGB::named(WHITE),
+    }
+}
+
+/** 石砖墙和石板地面，原来的外观 */
+pub struct DungeonTheme;
+
+impl MapTheme for DungeonTheme {
+    fn kind(&self) -> ThemeKind {
+        ThemeKind::Dungeon
+    }
+    fn tile_to_render(&self, tile_type: TileType) -> FontCharType {
+        match tile_type {
+            TileType::Floor => to_cp437('.'),
+            TileType::Wall => to_cp437('#'),
+            _ => terrain_glyph(tile_type),
+        }
+    }
+    fn tile_color(&self, tile_type: TileType) -> RGB {
+        terrain_color(tile_type)
+    }
+}
+
+/** 树木代替墙，泥土代替地板 */
+pub struct ForestTheme;
+
+impl MapTheme for ForestTheme {
+    fn kind(&self) -> ThemeKind {
+        ThemeKind::Forest
+    }
+    fn tile_to_render(&self, tile_type: TileType) -> FontCharType {
+        match tile_type {
+            TileType::Floor => to_cp437(';'),
+            TileType::Wall => to_cp437('"'),
+            _ => terrain_glyph(tile_type),
+        }
+    }
+    fn tile_color(&self, tile_type: TileType) -> RGB {
+        terrain_color(tile_type)
+    }
+}
+
+/** 泥土地面和土黄色的岩壁 */
+pub struct CavernTheme;
+
+impl MapTheme for CavernTheme {
+    fn kind(&self) -> ThemeKind {
+        ThemeKind::Cavern
+    }
+    fn tile_to_render(&self, tile_type: TileType) -> FontCharType {
+        match tile_type {
+            TileType::Floor => to_cp437(';'),
+            TileType::Wall => to_cp437('#'),
+            _ => terrain_glyph(tile_type),
+        }
+    }
+    fn tile_color(&self, tile_type: TileType) -> RGB {
+        match tile_type {
+            TileType::Wall => RGB::named(BURLYWOOD),
+            _ => terrain_color(tile_type),
+        }
+    }
+}
+
+/** 被岩浆映红的地下城 */
+pub struct LavaTheme;
+
+impl MapTheme for LavaTheme {
+    fn kind(&self) -> ThemeKind {
+        ThemeKind::Lava
+    }
+    fn tile_to_render(&self, tile_type: TileType) -> FontCharType {
+        match tile_type {
+            TileType::Floor => to_cp437('.'),
+            TileType::Wall => to_cp437('#'),
+            _ => terrain_glyph(tile_type),
+        }
+    }
+    fn tile_color(&self, tile_type: TileType) -> RGB {
+        match tile_type {
+            TileType::Wall => RGB::named(ORANGE_RED),
+            TileType::Floor => RGB::named(SANDY_BROWN),
+            _ => terrain_color(tile_type),
+        }
+    }
+}
@@ -0,0 +1,132 @@
+use super::MapArchitect;
+use crate::prelude::*;
+
+/** 默认尺寸地图上的种子数量 */
+const NUM_SEEDS: usize = 32;
+
+/** 已经连通的两个区域之间再开一扇门的概率的倒数 */
+const LOOP_CHANCE: usize = 40;
+
+/** 中心的区域至少占地图的1/4 */
+const MIN_FLOOR_DIVISOR: usize = 4;
+
+/** Voronoi蜂巢：每个格子归属最近的种子，不同区域交界的地方砌墙，再在墙上开门把区域连起来 */
+pub struct VoronoiArchitect {}
+
+impl MapArchitect for VoronoiArchitect {
+    fn build(&mut self, rng: &mut RandomNumberGenerator, mb: &mut MapBuilder) {
+        let num_seeds = mb.scaled(NUM_SEEDS).max(4);
+        let distance = match rng.range(0, 3) {
+            0 => DistanceAlg::Pythagoras,
+            1 => DistanceAlg::Manhattan,
+            _ => DistanceAlg::Chebyshev,
+        };
+        let seeds: Vec<Point> = (0..num_seeds)
+            .map(|_| {
+                Point::new(
+                    rng.range(1, mb.map.width - 1),
+                    rng.range(1, mb.map.height - 1),
+                )
+            })
+            .collect();
+        let membership = Self::membership(&mb.map, &seeds, distance);
+        Self::build_walls(&mut mb.map, &membership);
+        mb.take_snapshot();
+        Self::open_doors(rng, &mut mb.map, &membership, num_seeds);
+        mb.take_snapshot();
+    }
+
+    fn min_floor_divisor(&self) -> Option<usize> {
+        Some(MIN_FLOOR_DIVISOR)
+    }
+}
+
+impl VoronoiArchitect {
+    /** 每个格子属于哪个种子 */
+    fn membership(map: &Map, seeds: &[Point], distance: DistanceAlg) -> Vec<usize> {
+        (0..map.tiles.len())
+            .map(|idx| {
+                let pos = map.index_to_point2d(idx);
+                seeds
+                    .iter()
+                    .enumerate()
+                    .min_by(|(_, a), (_, b)| {
+                        let distance_a = distance.distance2d(pos, **a);
+                        let distance_b = distance.distance2d(pos, **b);
+                        distance_a.partial_cmp(&distance_b).unwrap()
+                    })
+                    .map_or(0, |(seed, _)| seed)
+            })
+            .collect()
+    }
+
+    /** 与右边或下边的格子属于不同种子时是墙，这样每个区域都被一格厚的墙围住 */
+    fn build_walls(map: &mut Map, membership: &[usize]) {
+        for y in 0..map.height {
+            for x in 0..map.width {
+                let idx = map.idx(x, y);
+                let wall = map.is_border(Point::new(x, y))
+                    || membership[map.idx(x + 1, y)] != membership[idx]
+                    || membership[map.idx(x, y + 1)] != membership[idx];
+                map.tiles[idx] = if wall {
+                    TileType::Wall
+                } else {
+                    TileType::Floor
+                };
+            }
+        }
+    }
+
+    /** 按随机顺序在墙上开门，两个区域还不连通时一定开，否则偶尔开一扇形成环路 */
+    fn open_doors(
+        rng: &mut RandomNumberGenerator,
+        map: &mut Map,
+        membership: &[usize],
+        num_seeds: usize,
+    ) {
+        let mut groups: Vec<usize> = (0..num_seeds).collect();
+        let mut candidates: Vec<(usize, usize, usize)> = Vec::new();
+        for y in 1..map.height - 1 {
+            for x in 1..map.width - 1 {
+                let idx = map.idx(x, y);
+                if map.tiles[idx] != TileType::Wall {
+                    continue;
+                }
+                for (a, b) in [
+                    (map.idx(x - 1, y), map.idx(x + 1, y)),
+                    (map.idx(x, y - 1), map.idx(x, y + 1)),
+                ] {
+                    if map.tiles[a] == TileType::Floor
+                        && map.tiles[b] == TileType::Floor
+                        && membership[a] != membership[b]
+                    {
+                        candidates.push((idx, membership[a], membership[b]));
+                    }
+                }
+            }
+        }
+        for i in (1..candidates.len()).rev() {
+            let j = rng.range(0, i + 1);
+            candidates.swap(i, j);
+        }
+        for (idx, a, b) in candidates {
+            let group_a = Self::find(&mut groups, a);
+            let group_b = Self::find(&mut groups, b);
+            if group_a != group_b {
+                groups[group_a] = group_b;
+                map.tiles[idx] = TileType::Floor;
+            } else if rng.range(0, LOOP_CHANCE) == 0 {
+                map.tiles[idx] = TileType::Floor;
+            }
+        }
+    }
+
+    fn find(groups: &mut [usize], seed: usize) -> usize {
+        let mut root = seed;
+        while groups[root] != root {
+            root = groups[root];
+        }
+        groups[seed] = root;
+        root
+    }
+}
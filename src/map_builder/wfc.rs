@@ -0,0 +1,237 @@
+use super::predab::Vault;
+use super::{
+    CellularAutomataArchitect, DrunkarksWalkArchitect, MapArchitect, MazeArchitect,
+    VoronoiArchitect,
+};
+use crate::prelude::*;
+
+/** 每个图块的边长 */
+const CHUNK_SIZE: i32 = 8;
+/** 从样本中截取图块的步长，小于图块边长时图块互相重叠，能得到更多的图块 */
+const CHUNK_STEP: i32 = 4;
+/** 求解失败或者连通区域太小时重试的次数，都失败就改用后备的构建器 */
+const MAX_WFC_ATTEMPTS: usize = 20;
+/** 中心的区域至少占地图的1/6，否则重试 */
+const MIN_FLOOR_DIVISOR: usize = 6;
+
+/** WFC从哪里学习图块 */
+pub enum WfcSource {
+    /** 先用另一个构建器生成一张同样大小的地图作为样本 */
+    Architect(Box<dyn MapArchitect>),
+    /** `resources/wfc`中手绘的样本 */
+    Sample(Vault),
+}
+
+/** 从样本中截取图块，记录每个图块四条边上的出口，再把边上出口吻合的图块拼成新的地图 */
+pub struct WfcArchitect {
+    source: WfcSource,
+}
+
+/** 一个图块和它四条边上哪些位置是地板 */
+struct Chunk {
+    tiles: Vec<TileType>,
+    exits: [u32; 4],
+}
+
+/** 图块的四条边，`opposite`是与之相邻的图块上对应的那条边 */
+#[derive(Clone, Copy)]
+enum Side {
+    North,
+    South,
+    West,
+    East,
+}
+
+impl Side {
+    const ALL: [Side; 4] = [Side::North, Side::South, Side::West, Side::East];
+
+    fn opposite(self) -> Self {
+        match self {
+            Side::North => Side::South,
+            Side::South => Side::North,
+            Side::West => Side::East,
+            Side::East => Side::West,
+        }
+    }
+
+    fn offset(self) -> Point {
+        match self {
+            Side::North => Point::new(0, -1),
+            Side::South => Point::new(0, 1),
+            Side::West => Point::new(-1, 0),
+            Side::East => Point::new(1, 0),
+        }
+    }
+}
+
+impl WfcSource {
+    /** 在几个构建器和所有手绘样本中随机选一个 */
+    pub fn random(rng: &mut RandomNumberGenerator, vaults: &Vaults) -> Self {
+        let samples = vaults.samples();
+        match rng.range(0, samples.len() + 3) {
+            0 => WfcSource::Architect(Box::new(CellularAutomataArchitect {})),
+            1 => WfcSource::Architect(Box::new(VoronoiArchitect {})),
+            2 => WfcSource::Architect(Box::new(MazeArchitect {})),
+            n => WfcSource::Sample(samples[n - 3].clone()),
+        }
+    }
+}
+
+impl WfcArchitect {
+    pub fn new(source: WfcSource) -> Self {
+        Self { source }
+    }
+}
+
+impl MapArchitect for WfcArchitect {
+    fn build(&mut self, rng: &mut RandomNumberGenerator, mb: &mut MapBuilder) {
+        let (sample, width, height) = match &mut self.source {
+            WfcSource::Architect(architect) => {
+                let mut sample = MapBuilder::empty(mb.map.width, mb.map.height);
+                sample.build_terrain(rng, architect.as_mut());
+                (sample.map.tiles, sample.map.width, sample.map.height)
+            }
+            WfcSource::Sample(vault) => {
+                let vault: &Vault = vault;
+                let tiles = (0..vault.height())
+                    .flat_map(|y| (0..vault.width()).map(move |x| vault.tile(x, y)))
+                    .collect();
+                (tiles, vault.width(), vault.height())
+            }
+        };
+        let chunks = Self::chunks(&sample, width, height);
+        for _ in 0..MAX_WFC_ATTEMPTS {
+            let Some(solution) = Self::solve(rng, &chunks, &mb.map) else {
+                continue;
+            };
+            Self::apply(&chunks, &solution, &mut mb.map);
+            mb.take_snapshot();
+            if mb.keep_central_region(MIN_FLOOR_DIVISOR) {
+                return;
+            }
+        }
+        //样本太单调拼不出像样的地图
+        match &mut self.source {
+            WfcSource::Architect(architect) => mb.build_terrain(rng, architect.as_mut()),
+            WfcSource::Sample(_) => DrunkarksWalkArchitect {}.build(rng, mb),
+        }
+    }
+}
+
+impl WfcArchitect {
+    /** 按`CHUNK_STEP`截取所有图块并去掉重复的 */
+    fn chunks(sample: &[TileType], width: i32, height: i32) -> Vec<Chunk> {
+        let mut chunks: Vec<Chunk> = Vec::new();
+        let mut y = 0;
+        while y + CHUNK_SIZE <= height {
+            let mut x = 0;
+            while x + CHUNK_SIZE <= width {
+                let tiles: Vec<TileType> = (0..CHUNK_SIZE)
+                    .flat_map(|cy| (0..CHUNK_SIZE).map(move |cx| (x + cx, y + cy)))
+                    .map(|(sx, sy)| {
+                        let idx = usize::try_from(sy * width + sx).unwrap_or(0);
+                        if sample[idx] == TileType::Wall {
+                            TileType::Wall
+                        } else {
+                            TileType::Floor
+                        }
+                    })
+                    .collect();
+                if !chunks.iter().any(|chunk| chunk.tiles == tiles) {
+                    let exits = Side::ALL.map(|side| Self::exits(&tiles, side));
+                    chunks.push(Chunk { tiles, exits });
+                }
+                x += CHUNK_STEP;
+            }
+            y += CHUNK_STEP;
+        }
+        chunks
+    }
+
+    /** 一条边上哪些位置是地板，每一位对应一个位置 */
+    fn exits(tiles: &[TileType], side: Side) -> u32 {
+        (0..CHUNK_SIZE)
+            .filter(|i| {
+                let (x, y) = match side {
+                    Side::North => (*i, 0),
+                    Side::South => (*i, CHUNK_SIZE - 1),
+                    Side::West => (0, *i),
+                    Side::East => (CHUNK_SIZE - 1, *i),
+                };
+                tiles[usize::try_from(y * CHUNK_SIZE + x).unwrap_or(0)] == TileType::Floor
+            })
+            .fold(0, |exits, i| exits | (1 << i))
+    }
+
+    /** 两边都没有出口，或者至少有一个出口对得上 */
+    fn compatible(chunk: &Chunk, side: Side, neighbor: &Chunk) -> bool {
+        let exits = chunk.exits[side as usize];
+        let neighbor_exits = neighbor.exits[side.opposite() as usize];
+        if exits == 0 || neighbor_exits == 0 {
+            exits == neighbor_exits
+        } else {
+            exits & neighbor_exits != 0
+        }
+    }
+
+    /** 从随机位置开始，每次在已放好的图块旁边选一格，放一个与所有邻居都吻合的图块，无路可走时失败 */
+    fn solve(rng: &mut RandomNumberGenerator, chunks: &[Chunk], map: &Map) -> Option<Vec<usize>> {
+        let columns = (map.width - 2) / CHUNK_SIZE;
+        let rows = (map.height - 2) / CHUNK_SIZE;
+        if chunks.is_empty() || columns < 1 || rows < 1 {
+            return None;
+        }
+        let cell_idx = |pos: Point| usize::try_from(pos.y * columns + pos.x).ok();
+        let in_grid = |pos: Point| pos.x >= 0 && pos.x < columns && pos.y >= 0 && pos.y < rows;
+        let mut grid: Vec<Option<usize>> = vec![None; usize::try_from(columns * rows).ok()?];
+        let mut next = Point::new(rng.range(0, columns), rng.range(0, rows));
+        loop {
+            let candidates: Vec<usize> = (0..chunks.len())
+                .filter(|candidate| {
+                    Side::ALL.iter().all(|side| {
+                        let neighbor = next + side.offset();
+                        !in_grid(neighbor)
+                            || grid[cell_idx(neighbor).unwrap_or(0)].is_none_or(|placed| {
+                                Self::compatible(&chunks[*candidate], *side, &chunks[placed])
+                            })
+                    })
+                })
+                .collect();
+            grid[cell_idx(next)?] = Some(*rng.random_slice_entry(&candidates)?);
+
+            let frontier: Vec<Point> = (0..rows)
+                .flat_map(|y| (0..columns).map(move |x| Point::new(x, y)))
+                .filter(|pos| grid[cell_idx(*pos).unwrap_or(0)].is_none())
+                .filter(|pos| {
+                    Side::ALL.iter().any(|side| {
+                        let neighbor = *pos + side.offset();
+                        in_grid(neighbor) && grid[cell_idx(neighbor).unwrap_or(0)].is_some()
+                    })
+                })
+                .collect();
+            let Some(pos) = rng.random_slice_entry(&frontier) else {
+                break;
+            };
+            next = *pos;
+        }
+        grid.into_iter().collect()
+    }
+
+    /** 把图块铺到地图上，边界和铺不满的边角都是墙 */
+    fn apply(chunks: &[Chunk], solution: &[usize], map: &mut Map) {
+        map.tiles.iter_mut().for_each(|t| *t = TileType::Wall);
+        let columns = (map.width - 2) / CHUNK_SIZE;
+        for (cell, chunk) in solution.iter().enumerate() {
+            let cell = i32::try_from(cell).unwrap_or(0);
+            let origin = Point::new(
+                1 + cell % columns * CHUNK_SIZE,
+                1 + cell / columns * CHUNK_SIZE,
+            );
+            for (i, tile) in chunks[*chunk].tiles.iter().enumerate() {
+                let i = i32::try_from(i).unwrap_or(0);
+                let idx = map.idx(origin.x + i % CHUNK_SIZE, origin.y + i / CHUNK_SIZE);
+                map.tiles[idx] = *tile;
+            }
+        }
+    }
+}
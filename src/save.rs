@@ -0,0 +1,279 @@
+use crate::prelude::*;
+use legion::serialize::{Canon, Registry};
+use serde::de::DeserializeSeed;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::fs;
+use std::path::Path;
+
+/** 存档文件的位置，退出时写入，游戏结束时删除 */
+pub const SAVE_FILE: &str = "savegame.ron";
+
+#[derive(Debug)]
+pub enum SaveError {
+    Io(std::io::Error),
+    Serialize(ron::Error),
+    Deserialize(ron::Error),
+    MissingResource(&'static str),
+}
+
+impl fmt::Display for SaveError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SaveError::Io(e) => write!(f, "无法读写存档文件 {SAVE_FILE}: {e}"),
+            SaveError::Serialize(e) => write!(f, "无法写入存档: {e}"),
+            SaveError::Deserialize(e) => write!(f, "存档文件 {SAVE_FILE} 已损坏: {e}"),
+            SaveError::MissingResource(name) => write!(f, "缺少资源{name}，无法存档"),
+        }
+    }
+}
+
+impl std::error::Error for SaveError {}
+
+/** 存档的全部内容，World先单独序列化为字符串再放进来 */
+#[derive(Deserialize)]
+struct SaveGame {
+    seed: u64,
+    turn_state: TurnState,
+    map: Map,
+    theme: ThemeKind,
+    camera: Camera,
+    rng: RandomNumberGenerator,
+    world: String,
+}
+
+/** 与`SaveGame`字段相同，写存档时借用资源而不必复制 */
+#[derive(Serialize)]
+struct SaveGameRef<'a> {
+    seed: u64,
+    turn_state: TurnState,
+    map: &'a Map,
+    theme: ThemeKind,
+    camera: &'a Camera,
+    rng: &'a RandomNumberGenerator,
+    world: String,
+}
+
+/** 读档后交还给State的内容 */
+pub struct LoadedGame {
+    pub seed: u64,
+    pub ecs: World,
+    pub resources: Resources,
+}
+
+/** 所有需要存档的组件都要在这里注册，名称一旦写入存档就不应修改 */
+fn registry() -> Registry<String> {
+    let mut registry = Registry::<String>::new(Canon::default());
+    registry.register::<()>("unit".to_string()); //消息实体的占位组件
+    registry.register::<Point>("Point".to_string());
+    registry.register::<Render>("Render".to_string());
+    registry.register::<Player>("Player".to_string());
+    registry.register::<Enemy>("Enemy".to_string());
+    registry.register::<Brain>("Brain".to_string());
+    registry.register::<Memory>("Memory".to_string());
+    registry.register::<Noise>("Noise".to_string());
+    registry.register::<Projectile>("Projectile".to_string());
+    registry.register::<WantsToMove>("WantsToMove".to_string());
+    registry.register::<WantsToAttack>("WantsToAttack".to_string());
+    registry.register::<Health>("Health".to_string());
+    registry.register::<Name>("Name".to_string());
+    registry.register::<Damage>("Damage".to_string());
+    registry.register::<Armor>("Armor".to_string());
+    registry.register::<Equippable>("Equippable".to_string());
+    registry.register::<Equipped>("Equipped".to_string());
+    registry.register::<Item>("Item".to_string());
+    registry.register::<AmuletOfYala>("AmuletOfYala".to_string());
+    registry.register::<Carried>("Carried".to_string());
+    registry.register::<WantsToPickUp>("WantsToPickUp".to_string());
+    registry.register::<ProvidesHealing>("ProvidesHealing".to_string());
+    registry.register::<ProvidesDungeonMap>("ProvidesDungeonMap".to_string());
+    registry.register::<ProvidesTeleport>("ProvidesTeleport".to_string());
+    registry.register::<ProvidesAreaDamage>("ProvidesAreaDamage".to_string());
+    registry.register::<WantsToUseItem>("WantsToUseItem".to_string());
+    registry.register::<FeildOfView>("FeildOfView".to_string());
+    registry
+}
+
+pub fn has_save() -> bool {
+    Path::new(SAVE_FILE).exists()
+}
+
+pub fn delete_save() {
+    if has_save()
+        && let Err(e) = fs::remove_file(SAVE_FILE)
+    {
+        eprintln!("{}", SaveError::Io(e));
+    }
+}
+
+pub fn save_game(ecs: &World, resources: &Resources, seed: u64) -> Result<(), SaveError> {
+    let text = save_to_string(ecs, resources, seed)?;
+    fs::write(SAVE_FILE, text).map_err(SaveError::Io)
+}
+
+pub fn load_game() -> Result<LoadedGame, SaveError> {
+    let text = fs::read_to_string(SAVE_FILE).map_err(SaveError::Io)?;
+    load_from_str(&text)
+}
+
+fn save_to_string(ecs: &World, resources: &Resources, seed: u64) -> Result<String, SaveError> {
+    let registry = registry();
+    let world = ron::ser::to_string(&ecs.as_serializable(any(), &registry))
+        .map_err(SaveError::Serialize)?;
+    let map = resources
+        .get::<Map>()
+        .ok_or(SaveError::MissingResource("Map"))?;
+    let theme = resources
+        .get::<Box<dyn MapTheme>>()
+        .ok_or(SaveError::MissingResource("MapTheme"))?
+        .kind();
+    let camera = resources
+        .get::<Camera>()
+        .ok_or(SaveError::MissingResource("Camera"))?;
+    let turn_state = resources
+        .get::<TurnState>()
+        .ok_or(SaveError::MissingResource("TurnState"))?;
+    let rng = resources
+        .get::<RandomNumberGenerator>()
+        .ok_or(SaveError::MissingResource("RandomNumberGenerator"))?;
+    let save = SaveGameRef {
+        seed,
+        turn_state: *turn_state,
+        map: &map,
+        theme,
+        camera: &camera,
+        rng: &rng,
+        world,
+    };
+    ron::ser::to_string_pretty(&save, ron::ser::PrettyConfig::default())
+        .map_err(SaveError::Serialize)
+}
+
+fn load_from_str(text: &str) -> Result<LoadedGame, SaveError> {
+    let save: SaveGame = ron::de::from_str(text).map_err(|e| SaveError::Deserialize(e.code))?;
+    let registry = registry();
+    let mut deserializer =
+        ron::Deserializer::from_str(&save.world).map_err(|e| SaveError::Deserialize(e.code))?;
+    let ecs = registry
+        .as_deserialize()
+        .deserialize(&mut deserializer)
+        .map_err(SaveError::Deserialize)?;
+    let mut resources = Resources::default();
+    resources.insert(SpatialIndex::build(&ecs, &save.map));
+    resources.insert(save.map);
+    resources.insert(save.theme.theme());
+    resources.insert(save.camera);
+    resources.insert(save.turn_state);
+    resources.insert(save.rng);
+    resources.insert(FlowFields::default());
+    Ok(LoadedGame {
+        seed: save.seed,
+        ecs,
+        resources,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn find(ecs: &World, name: &str) -> Entity {
+        *<(Entity, &Name)>::query()
+            .iter(ecs)
+            .find(|(_, n)| n.0 == name)
+            .unwrap_or_else(|| panic!("{name} should survive loading"))
+            .0
+    }
+
+    /** 不经过存档文件，直接把序列化的文本读回来 */
+    #[test]
+    fn save_and_load_round_trip() {
+        let mut ecs = World::default();
+        let player = ecs.push((
+            Player { depth: 2 },
+            Name("Player".to_string()),
+            Point::new(3, 3),
+            Health {
+                current: 7,
+                max: 10,
+            },
+        ));
+        let goblin = ecs.push((Enemy, Name("Goblin".to_string()), Point::new(8, 3)));
+        ecs.push((Item, Name("Sword".to_string()), Carried(player)));
+        ecs.push((
+            (),
+            WantsToAttack {
+                attacker: goblin,
+                victim: player,
+            },
+        ));
+        ecs.push((
+            Point::new(7, 3),
+            Projectile {
+                shooter: goblin,
+                path: vec![Point::new(6, 3), Point::new(5, 3)],
+            },
+        ));
+
+        let mut map = Map::new(30, 20);
+        let wall = map.idx(5, 5);
+        map.tiles[wall] = TileType::Wall;
+        map.revealed_tiles[wall] = true;
+        let camera = Camera::new(Point::new(3, 3), &map);
+        let mut resources = Resources::default();
+        resources.insert(map.clone());
+        resources.insert(ThemeKind::Cavern.theme());
+        resources.insert(camera);
+        resources.insert(TurnState::MonsterTurn);
+        resources.insert(RandomNumberGenerator::seeded(1));
+
+        let text = save_to_string(&ecs, &resources, 42).expect("game should save");
+        let loaded = load_from_str(&text).expect("saved game should load");
+        assert_eq!(loaded.seed, 42);
+
+        //读档后实体是新的，引用要指向同一个名字的实体
+        let ecs = &loaded.ecs;
+        let player = find(ecs, "Player");
+        let goblin = find(ecs, "Goblin");
+        let sword = ecs.entry_ref(find(ecs, "Sword")).unwrap();
+        assert_eq!(sword.get_component::<Carried>().unwrap().0, player);
+        let attacks: Vec<WantsToAttack> = <&WantsToAttack>::query().iter(ecs).copied().collect();
+        assert_eq!(
+            attacks,
+            vec![WantsToAttack {
+                attacker: goblin,
+                victim: player,
+            }]
+        );
+        let projectiles: Vec<&Projectile> = <&Projectile>::query().iter(ecs).collect();
+        assert_eq!(projectiles.len(), 1);
+        assert_eq!(projectiles[0].shooter, goblin);
+        assert_eq!(projectiles[0].path, vec![Point::new(6, 3), Point::new(5, 3)]);
+        let player_entry = ecs.entry_ref(player).unwrap();
+        assert_eq!(player_entry.get_component::<Player>().unwrap().depth, 2);
+        assert_eq!(player_entry.get_component::<Health>().unwrap().current, 7);
+
+        let resources = &loaded.resources;
+        let loaded_map = resources.get::<Map>().unwrap();
+        assert_eq!((loaded_map.width, loaded_map.height), (map.width, map.height));
+        assert_eq!(loaded_map.tiles, map.tiles);
+        assert_eq!(loaded_map.revealed_tiles, map.revealed_tiles);
+        let loaded_camera = resources.get::<Camera>().unwrap();
+        let camera = Camera::new(Point::new(3, 3), &map);
+        assert_eq!(
+            (loaded_camera.left_x, loaded_camera.right_x),
+            (camera.left_x, camera.right_x)
+        );
+        assert_eq!(
+            (loaded_camera.top_y, loaded_camera.bottom_y),
+            (camera.top_y, camera.bottom_y)
+        );
+        assert_eq!(*resources.get::<TurnState>().unwrap(), TurnState::MonsterTurn);
+        assert_eq!(
+            resources.get::<Box<dyn MapTheme>>().unwrap().kind(),
+            ThemeKind::Cavern
+        );
+        let index = resources.get::<SpatialIndex>().unwrap();
+        assert_eq!(index.blocker_at(Point::new(3, 3)), Some(player));
+    }
+}
@@ -0,0 +1,140 @@
+use crate::prelude::*;
+
+/** 每个地块上有哪些实体，以及哪个实体挡住了这一格，按位置查询都是O(1)。
+ * 生成关卡和读档时整体构建，之后由移动、拾取和死亡的相关系统逐个更新 */
+pub struct SpatialIndex {
+    width: i32,
+    height: i32,
+    contents: Vec<Vec<Entity>>,
+    blockers: Vec<Option<Entity>>,
+}
+
+impl SpatialIndex {
+    /** 有生命值的实体会挡住所在的地块，飞行中的投射物不记录 */
+    pub fn build(ecs: &World, map: &Map) -> Self {
+        let len = map.tiles.len();
+        let mut index = Self {
+            width: map.width,
+            height: map.height,
+            contents: vec![Vec::new(); len],
+            blockers: vec![None; len],
+        };
+        <(Entity, &Point, Option<&Health>)>::query()
+            .filter(!component::<Projectile>())
+            .for_each(ecs, |(entity, pos, health)| {
+                index.insert(*entity, *pos, health.is_some());
+            });
+        index
+    }
+
+    fn idx(&self, pos: Point) -> Option<usize> {
+        if pos.x < 0 || pos.x >= self.width || pos.y < 0 || pos.y >= self.height {
+            return None;
+        }
+        usize::try_from(pos.y * self.width + pos.x).ok()
+    }
+
+    pub fn insert(&mut self, entity: Entity, pos: Point, blocks: bool) {
+        if let Some(idx) = self.idx(pos) {
+            self.contents[idx].push(entity);
+            if blocks && self.blockers[idx].is_none() {
+                self.blockers[idx] = Some(entity);
+            }
+        }
+    }
+
+    pub fn remove(&mut self, entity: Entity, pos: Point) {
+        if let Some(idx) = self.idx(pos) {
+            self.contents[idx].retain(|e| *e != entity);
+            if self.blockers[idx] == Some(entity) {
+                self.blockers[idx] = None;
+            }
+        }
+    }
+
+    /** 移动后实体是否挡路保持不变 */
+    pub fn move_entity(&mut self, entity: Entity, from: Point, to: Point) {
+        let blocks = self.blocker_at(from) == Some(entity);
+        self.remove(entity, from);
+        self.insert(entity, to, blocks);
+    }
+
+    pub fn entities_at(&self, pos: Point) -> &[Entity] {
+        self.idx(pos).map_or(&[], |idx| &self.contents[idx])
+    }
+
+    pub fn blocker_at(&self, pos: Point) -> Option<Entity> {
+        self.idx(pos).and_then(|idx| self.blockers[idx])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn setup() -> (World, SpatialIndex) {
+        let map = Map::new(10, 10);
+        let world = World::default();
+        let index = SpatialIndex::build(&world, &map);
+        (world, index)
+    }
+
+    #[test]
+    fn insert_and_remove() {
+        let (mut world, mut index) = setup();
+        let monster = world.push((Point::new(3, 3),));
+        let item = world.push((Point::new(3, 3),));
+        let pos = Point::new(3, 3);
+        index.insert(item, pos, false);
+        index.insert(monster, pos, true);
+        assert_eq!(index.entities_at(pos), &[item, monster]);
+        assert_eq!(index.blocker_at(pos), Some(monster));
+
+        index.remove(monster, pos);
+        assert_eq!(index.entities_at(pos), &[item]);
+        assert_eq!(index.blocker_at(pos), None);
+
+        //地图之外的位置不记录，查询时也是空的
+        index.insert(monster, Point::new(-1, 20), true);
+        assert!(index.entities_at(Point::new(-1, 20)).is_empty());
+        assert_eq!(index.blocker_at(Point::new(-1, 20)), None);
+    }
+
+    #[test]
+    fn move_entity_keeps_blocking() {
+        let (mut world, mut index) = setup();
+        let (from, to) = (Point::new(2, 2), Point::new(2, 3));
+        let monster = world.push((from,));
+        let item = world.push((from,));
+        index.insert(monster, from, true);
+        index.insert(item, from, false);
+
+        index.move_entity(monster, from, to);
+        assert_eq!(index.blocker_at(from), None);
+        assert_eq!(index.entities_at(from), &[item]);
+        assert_eq!(index.blocker_at(to), Some(monster));
+        assert_eq!(index.entities_at(to), &[monster]);
+
+        index.move_entity(item, from, to);
+        assert_eq!(index.blocker_at(to), Some(monster));
+        assert_eq!(index.entities_at(to), &[monster, item]);
+    }
+
+    #[test]
+    fn build_skips_projectiles() {
+        let map = Map::new(10, 10);
+        let mut world = World::default();
+        let pos = Point::new(4, 4);
+        let monster = world.push((pos, Health { current: 1, max: 1 }));
+        world.push((
+            pos,
+            Projectile {
+                shooter: monster,
+                path: Vec::new(),
+            },
+        ));
+        let index = SpatialIndex::build(&world, &map);
+        assert_eq!(index.entities_at(pos), &[monster]);
+        assert_eq!(index.blocker_at(pos), Some(monster));
+    }
+}
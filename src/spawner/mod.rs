@@ -0,0 +1,49 @@
+use crate::prelude::*;
+
+mod template;
+pub use template::*;
+
+const PLAYER_MAX_HEALTH: i32 = 50;
+pub fn spawn_player(ecs: &mut World, pos: Point) {
+    ecs.push((
+        Player { depth: 1 },
+        pos,
+        Render {
+            color: ColorPair::new(WHITE, BLACK),
+            glyph: to_cp437('@'),
+        },
+        Health {
+            current: PLAYER_MAX_HEALTH,
+            max: PLAYER_MAX_HEALTH,
+        },
+        FeildOfView::new(8),
+        Damage(DiceType::new(1, 4, 0)),
+    ));
+}
+
+pub fn spawn_amulet_of_yala(ecs: &mut World, pos: Point) {
+    ecs.push((
+        Item,
+        AmuletOfYala,
+        pos,
+        Render {
+            color: ColorPair::new(WHITE, BLACK),
+            glyph: to_cp437('|'),
+        },
+        Name("Amulet of Yala".to_string()),
+    ));
+}
+
+/** 在新生成的地图上按模板放置怪物和物品，并在最深一层放置护身符，其余层的楼梯由构建链放好 */
+pub fn spawn_level(
+    ecs: &mut World,
+    rng: &mut RandomNumberGenerator,
+    templates: &Templates,
+    map_builder: &mut MapBuilder,
+    depth: u32,
+) {
+    if depth == DUNGEON_DEPTH {
+        spawn_amulet_of_yala(ecs, map_builder.amulet_start);
+    }
+    templates.spawn_entities(ecs, rng, depth, &map_builder.monster_spawns);
+}
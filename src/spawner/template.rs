@@ -0,0 +1,261 @@
+use crate::prelude::*;
+use serde::Deserialize;
+use std::fmt;
+use std::fs::File;
+
+/** 模板文件的默认位置，与字体等资源放在一起 */
+pub const TEMPLATE_FILE: &str = "resources/template.ron";
+
+#[derive(Clone, Copy, Debug, PartialEq, Deserialize)]
+pub enum EntityType {
+    Enemy,
+    Item,
+}
+
+/** 物品被使用时产生的效果，每种效果对应一个组件，由`use_items`系统统一处理 */
+#[derive(Clone, Copy, Debug, PartialEq, Deserialize)]
+pub enum ItemEffect {
+    Healing(i32),
+    MagicMap,
+    Teleport,
+    AreaDamage { damage: i32, radius: i32 },
+}
+
+/** 模板文件中的一条实体描述 */
+#[derive(Clone, Debug, Deserialize)]
+pub struct Template {
+    pub entity_type: EntityType,
+    pub name: String,
+    pub glyph: char,
+    pub color: String, //形如"#FFFFFF"的十六进制颜色
+    pub hp: Option<i32>,
+    pub fov_radius: Option<i32>,
+    pub brain: Option<Vec<(Behavior, f32)>>, //怪物的行为和权重
+    pub damage: Option<String>, //骰子记法，如"1d6+1"
+    pub armor: Option<i32>,
+    pub equip: Option<EquipSlot>, //物品的装备栏位
+    pub provides: Option<Vec<ItemEffect>>, //物品的使用效果
+    pub weight: i32,    //生成权重，越大越常见
+    pub depth_weight: Option<i32>, //比min_depth每深一层增加的权重
+    pub min_depth: u32, //最浅出现的层数
+    pub max_depth: u32, //最深出现的层数
+}
+
+impl Template {
+    /** 在指定层数的生成权重，层数越深按`depth_weight`变化 */
+    fn weight_at(&self, depth: u32) -> i32 {
+        let levels = i32::try_from(depth.saturating_sub(self.min_depth)).unwrap_or(0);
+        self.weight + self.depth_weight.unwrap_or(0) * levels
+    }
+}
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct Templates {
+    pub entities: Vec<Template>,
+}
+
+#[derive(Debug)]
+pub enum TemplateError {
+    Io(std::io::Error),
+    Parse(ron::error::SpannedError),
+    Invalid {
+        index: usize,
+        name: String,
+        reason: String,
+    },
+}
+
+impl fmt::Display for TemplateError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TemplateError::Io(e) => write!(f, "无法读取模板文件 {TEMPLATE_FILE}: {e}"),
+            TemplateError::Parse(e) => write!(f, "模板文件 {TEMPLATE_FILE} 格式错误: {e}"),
+            TemplateError::Invalid {
+                index,
+                name,
+                reason,
+            } => write!(f, "模板第{index}项\"{name}\"无效: {reason}"),
+        }
+    }
+}
+
+impl std::error::Error for TemplateError {}
+
+impl Templates {
+    pub fn load() -> Result<Self, TemplateError> {
+        let file = File::open(TEMPLATE_FILE).map_err(TemplateError::Io)?;
+        let templates: Templates = ron::de::from_reader(file).map_err(TemplateError::Parse)?;
+        templates.validate()?;
+        Ok(templates)
+    }
+
+    /** 检查每一项的取值是否合理，出错时指明是哪一项 */
+    fn validate(&self) -> Result<(), TemplateError> {
+        for (index, template) in self.entities.iter().enumerate() {
+            let invalid = |reason: &str| TemplateError::Invalid {
+                index: index + 1,
+                name: template.name.clone(),
+                reason: reason.to_string(),
+            };
+            if template.name.trim().is_empty() {
+                return Err(invalid("name不能为空"));
+            }
+            if !template.glyph.is_ascii() {
+                return Err(invalid("glyph必须是ASCII字符"));
+            }
+            if RGB::from_hex(&template.color).is_err() {
+                return Err(invalid("color必须是\"#RRGGBB\"形式"));
+            }
+            if template.weight < 1 {
+                return Err(invalid("weight必须大于0"));
+            }
+            if template.min_depth < 1 || template.min_depth > template.max_depth {
+                return Err(invalid("min_depth必须大于0且不大于max_depth"));
+            }
+            if let Some(damage) = &template.damage
+                && parse_dice_string(damage).is_err()
+            {
+                return Err(invalid("damage必须是骰子记法，如\"1d6+1\""));
+            }
+            if template.armor.is_some_and(|armor| armor < 0) {
+                return Err(invalid("armor不能为负数"));
+            }
+            if template.entity_type == EntityType::Enemy {
+                if template.hp.is_none_or(|hp| hp < 1) {
+                    return Err(invalid("敌人必须有大于0的hp"));
+                }
+                if template.fov_radius.is_none_or(|radius| radius < 1) {
+                    return Err(invalid("敌人必须有大于0的fov_radius"));
+                }
+                if template.brain.as_ref().is_none_or(Vec::is_empty) {
+                    return Err(invalid("敌人必须在brain中指定至少一个行为"));
+                }
+                if template.provides.is_some() || template.equip.is_some() {
+                    return Err(invalid("只有物品可以有provides和equip"));
+                }
+            } else if template.brain.is_some() {
+                return Err(invalid("只有敌人可以有brain"));
+            }
+            for (behavior, weight) in template.brain.iter().flatten() {
+                if *weight <= 0.0 {
+                    return Err(invalid("行为的权重必须大于0"));
+                }
+                match behavior {
+                    Behavior::Shoot { range } if *range < 2 => {
+                        return Err(invalid("Shoot的range必须大于1"));
+                    }
+                    Behavior::Flee { below } if *below < 1 || Some(*below) >= template.hp => {
+                        return Err(invalid("Flee的below必须大于0且小于hp"));
+                    }
+                    Behavior::Guard { radius } | Behavior::CallAllies { radius } if *radius < 1 => {
+                        return Err(invalid("Guard和CallAllies的radius必须大于0"));
+                    }
+                    _ => {}
+                }
+            }
+            for effect in template.provides.iter().flatten() {
+                match effect {
+                    ItemEffect::Healing(amount) if *amount < 1 => {
+                        return Err(invalid("Healing的数值必须大于0"));
+                    }
+                    ItemEffect::AreaDamage { damage, radius } if *damage < 1 || *radius < 1 => {
+                        return Err(invalid("AreaDamage的damage和radius必须大于0"));
+                    }
+                    _ => {}
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /** 按权重随机挑选一个在当前层可以出现的模板 */
+    fn choose(&self, rng: &mut RandomNumberGenerator, depth: u32) -> Option<&Template> {
+        let available: Vec<(&Template, i32)> = self
+            .entities
+            .iter()
+            .filter(|t| depth >= t.min_depth && depth <= t.max_depth)
+            .map(|t| (t, t.weight_at(depth)))
+            .filter(|(_, weight)| *weight > 0)
+            .collect();
+        let total_weight: i32 = available.iter().map(|(_, weight)| weight).sum();
+        if total_weight == 0 {
+            return None;
+        }
+        let mut roll = rng.range(0, total_weight);
+        available.into_iter().find_map(|(t, weight)| {
+            roll -= weight;
+            (roll < 0).then_some(t)
+        })
+    }
+
+    pub fn spawn_entities(
+        &self,
+        ecs: &mut World,
+        rng: &mut RandomNumberGenerator,
+        depth: u32,
+        spawn_points: &[Point],
+    ) {
+        let mut commands = CommandBuffer::new(ecs);
+        for pt in spawn_points {
+            if let Some(template) = self.choose(rng, depth) {
+                Self::spawn_entity(*pt, template, &mut commands);
+            }
+        }
+        commands.flush(ecs);
+    }
+
+    fn spawn_entity(pt: Point, template: &Template, commands: &mut CommandBuffer) {
+        let color = RGB::from_hex(&template.color).unwrap_or_else(|_| RGB::named(WHITE));
+        let entity = commands.push((
+            pt,
+            Render {
+                color: ColorPair::new(color, BLACK),
+                glyph: to_cp437(template.glyph),
+            },
+            Name(template.name.clone()),
+        ));
+        match template.entity_type {
+            EntityType::Item => commands.add_component(entity, Item),
+            EntityType::Enemy => {
+                let hp = template.hp.unwrap_or(1);
+                commands.add_component(entity, Enemy);
+                commands.add_component(entity, FeildOfView::new(template.fov_radius.unwrap_or(6)));
+                commands.add_component(
+                    entity,
+                    Health {
+                        current: hp,
+                        max: hp,
+                    },
+                );
+                commands.add_component(
+                    entity,
+                    Brain {
+                        behaviors: template.brain.clone().unwrap_or_default(),
+                        home: pt,
+                    },
+                );
+            }
+        }
+        if let Some(damage) = template.damage.as_deref().and_then(|d| parse_dice_string(d).ok()) {
+            commands.add_component(entity, Damage(damage));
+        }
+        if let Some(armor) = template.armor {
+            commands.add_component(entity, Armor(armor));
+        }
+        if let Some(slot) = template.equip {
+            commands.add_component(entity, Equippable(slot));
+        }
+        for effect in template.provides.iter().flatten() {
+            match *effect {
+                ItemEffect::Healing(amount) => {
+                    commands.add_component(entity, ProvidesHealing { amount });
+                }
+                ItemEffect::MagicMap => commands.add_component(entity, ProvidesDungeonMap),
+                ItemEffect::Teleport => commands.add_component(entity, ProvidesTeleport),
+                ItemEffect::AreaDamage { damage, radius } => {
+                    commands.add_component(entity, ProvidesAreaDamage { damage, radius });
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,605 @@
+use crate::prelude::*;
+use std::collections::HashSet;
+
+/** 与玩家的距离小于这个值时可以近战，只算上下左右相邻 */
+const MELEE_DISTANCE: f32 = 1.2;
+/** 远程攻击者与玩家的距离小于这个值时后退，拉开距离再射击 */
+const MIN_SHOOT_DISTANCE: f32 = 2.0;
+/** 到达记得的位置后搜索的回合数 */
+const SEARCH_TURNS: i32 = 5;
+/** 搜索时离记得的位置最远的距离 */
+const SEARCH_RADIUS: f32 = 3.0;
+
+/** 一只怪物这一回合面对的情况，行为的得分只由它决定 */
+struct Situation {
+    entity: Entity,
+    pos: Point,
+    health: Health,
+    home: Point,
+    memory: Option<Memory>,
+    sees_player: bool,
+    player_distance: f32,
+    potion: Option<(Entity, Point)>,
+}
+
+/** 执行行为时共用的状态 */
+struct Planner<'a> {
+    map: &'a Map,
+    player: Entity,
+    player_pos: Point,
+    index: &'a SpatialIndex,
+    //这一回合已经有怪物要走进去的地块
+    claimed: HashSet<Point>,
+    //这一回合已经有怪物要离开的地块，后面的怪物可以跟着走进去
+    vacated: HashSet<Point>,
+    //还没发现玩家的怪物，可以被同伴呼叫
+    unaware: Vec<(Entity, Point)>,
+    used_potions: HashSet<Entity>,
+    flow_fields: &'a mut FlowFields,
+}
+
+#[system]
+#[read_component(Point)]
+#[read_component(Brain)]
+#[read_component(Memory)]
+#[read_component(Noise)]
+#[read_component(FeildOfView)]
+#[read_component(Health)]
+#[read_component(Player)]
+#[read_component(Item)]
+#[read_component(ProvidesHealing)]
+pub fn ai(
+    #[resource] map: &Map,
+    #[resource] rng: &mut RandomNumberGenerator,
+    #[resource] flow_fields: &mut FlowFields,
+    #[resource] index: &SpatialIndex,
+    ecs: &SubWorld,
+    commands: &mut CommandBuffer,
+) {
+    let mut player = <(Entity, &Point)>::query().filter(component::<Player>());
+    let Some((player, player_pos)) = player.iter(ecs).next() else {
+        return;
+    };
+    let mut brains = <(
+        Entity,
+        &Point,
+        &Brain,
+        &Health,
+        &FeildOfView,
+        Option<&Memory>,
+    )>::query();
+    let monsters: Vec<_> = brains.iter(ecs).collect();
+    let noises: Vec<Noise> = <&Noise>::query().iter(ecs).copied().collect();
+    <Entity>::query()
+        .filter(component::<Noise>())
+        .for_each(ecs, |noise| commands.remove(*noise));
+    //地上的治疗药水，被拾取的物品没有位置
+    let potions: Vec<(Entity, Point)> = <(Entity, &Point)>::query()
+        .filter(component::<Item>() & component::<ProvidesHealing>())
+        .iter(ecs)
+        .map(|(entity, pos)| (*entity, *pos))
+        .collect();
+    let mut planner = Planner {
+        map,
+        player: *player,
+        player_pos: *player_pos,
+        index,
+        claimed: HashSet::new(),
+        vacated: HashSet::new(),
+        unaware: monsters
+            .iter()
+            .filter(|(_, _, _, _, fov, memory)| {
+                memory.is_none() && !fov.visible_tiles.contains(player_pos)
+            })
+            .map(|(entity, pos, _, _, _, _)| (**entity, **pos))
+            .collect(),
+        used_potions: HashSet::new(),
+        flow_fields,
+    };
+
+    for (entity, pos, brain, health, fov, memory) in &monsters {
+        let sees_player = fov.visible_tiles.contains(player_pos);
+        #[allow(clippy::cast_precision_loss)]
+        let heard = noises.iter().find(|noise| {
+            DistanceAlg::Pythagoras.distance2d(**pos, noise.pos) <= noise.radius as f32
+        });
+        //看到的比听到的可靠，都没有时沿用原来的记忆
+        let new_memory = match (sees_player, heard) {
+            (true, _) => Some(remember(*player_pos)),
+            (false, Some(noise)) => Some(remember(noise.pos)),
+            (false, None) => memory.copied(),
+        };
+        if let Some(new_memory) = new_memory
+            && Some(&new_memory) != *memory
+        {
+            commands.add_component(**entity, new_memory);
+        }
+        let potion = potions
+            .iter()
+            .filter(|(item, pos)| {
+                fov.visible_tiles.contains(pos) && !planner.used_potions.contains(item)
+            })
+            .min_by(|(_, a), (_, b)| {
+                let distance = |p: &Point| DistanceAlg::Pythagoras.distance2d(**pos, *p);
+                distance(a).total_cmp(&distance(b))
+            })
+            .copied();
+        let situation = Situation {
+            entity: **entity,
+            pos: **pos,
+            health: **health,
+            home: brain.home,
+            memory: new_memory,
+            sees_player,
+            player_distance: DistanceAlg::Pythagoras.distance2d(**pos, *player_pos),
+            potion,
+        };
+        if let Some(behavior) = planner.choose(brain, &situation) {
+            planner.act(behavior, &situation, rng, commands);
+        }
+    }
+}
+
+impl Planner<'_> {
+    /** 得分乘以权重后最高的行为，得分相同时选排在前面的行为 */
+    fn choose(&self, brain: &Brain, s: &Situation) -> Option<Behavior> {
+        brain
+            .behaviors
+            .iter()
+            .map(|(behavior, weight)| (*behavior, weight * self.score(*behavior, s)))
+            .filter(|(_, score)| *score > 0.0)
+            .fold(
+                None,
+                |best: Option<(Behavior, f32)>, candidate| match best {
+                    Some(best) if best.1 >= candidate.1 => Some(best),
+                    _ => Some(candidate),
+                },
+            )
+            .map(|(behavior, _)| behavior)
+    }
+
+    /** 行为在当前情况下的得分，0表示现在做不了；最终得分还要乘以模板中配置的权重 */
+    #[allow(clippy::cast_precision_loss)]
+    fn score(&self, behavior: Behavior, s: &Situation) -> f32 {
+        match behavior {
+            Behavior::Wander => 0.1,
+            Behavior::Chase if s.sees_player => 0.6,
+            Behavior::Shoot { .. } if s.sees_player => 0.7,
+            Behavior::Chase | Behavior::Shoot { .. } if s.memory.is_some() => 0.4,
+            //忘掉玩家以后回出生点
+            Behavior::Chase if s.pos != s.home => 0.05,
+            Behavior::Flee { below } if s.sees_player && s.health.current <= below => 1.0,
+            Behavior::Guard { radius }
+                if s.sees_player && near(s.home, self.player_pos, radius) =>
+            {
+                0.7
+            }
+            Behavior::Guard { radius } if s.memory.is_some_and(|m| near(s.home, m.pos, radius)) => {
+                0.4
+            }
+            Behavior::Guard { .. } => 0.2,
+            Behavior::CallAllies { radius }
+                if s.sees_player && self.allies_in(s, radius).next().is_some() =>
+            {
+                0.9
+            }
+            //伤得越重越想喝药
+            Behavior::UseItem if s.potion.is_some() => {
+                1.0 - s.health.current as f32 / s.health.max as f32
+            }
+            _ => 0.0,
+        }
+    }
+
+    fn act(
+        &mut self,
+        behavior: Behavior,
+        s: &Situation,
+        rng: &mut RandomNumberGenerator,
+        commands: &mut CommandBuffer,
+    ) {
+        match behavior {
+            Behavior::Wander => {
+                let destination = match rng.range(0, 4) {
+                    0 => Point::new(1, 0),
+                    1 => Point::new(-1, 0),
+                    2 => Point::new(0, 1),
+                    _ => Point::new(0, -1),
+                } + s.pos;
+                self.step(s, destination, commands);
+            }
+            Behavior::Chase => {
+                if s.sees_player {
+                    self.chase(s, commands);
+                } else if let Some(memory) = s.memory {
+                    self.investigate(s, memory, rng, commands);
+                } else {
+                    self.go_home(s, commands);
+                }
+            }
+            Behavior::Shoot { range } => {
+                if s.sees_player {
+                    self.shoot(s, range, commands);
+                } else if let Some(memory) = s.memory {
+                    self.investigate(s, memory, rng, commands);
+                }
+            }
+            Behavior::Flee { .. } => self.flee(s, commands),
+            Behavior::Guard { radius } => {
+                if s.sees_player && near(s.home, self.player_pos, radius) {
+                    self.chase(s, commands);
+                } else if let Some(memory) = s.memory
+                    && near(s.home, memory.pos, radius)
+                {
+                    self.investigate(s, memory, rng, commands);
+                } else {
+                    self.go_home(s, commands);
+                }
+            }
+            Behavior::CallAllies { radius } => {
+                let allies: Vec<Entity> = self.allies_in(s, radius).collect();
+                for ally in allies {
+                    commands.add_component(ally, remember(self.player_pos));
+                    self.unaware.retain(|(entity, _)| *entity != ally);
+                }
+            }
+            Behavior::UseItem => {
+                let Some((potion, potion_pos)) = s.potion else {
+                    return;
+                };
+                if potion_pos == s.pos {
+                    self.used_potions.insert(potion);
+                    commands.push((
+                        (),
+                        WantsToUseItem {
+                            used_by: s.entity,
+                            item: potion,
+                        },
+                    ));
+                } else if let Some(next) = self.path_step(s.pos, potion_pos) {
+                    self.step(s, next, commands);
+                }
+            }
+        }
+    }
+
+    /** 沿Dijkstra图逼近看到的玩家，相邻时攻击 */
+    fn chase(&mut self, s: &Situation, commands: &mut CommandBuffer) {
+        if s.player_distance < MELEE_DISTANCE {
+            self.attack(s, commands);
+        } else if let Some(next) = self.downhill(s.pos) {
+            self.step(s, next, commands);
+        }
+    }
+
+    /** 赶往记得的位置，到了以后在附近随机搜索几个回合，还找不到玩家就忘掉 */
+    fn investigate(
+        &mut self,
+        s: &Situation,
+        memory: Memory,
+        rng: &mut RandomNumberGenerator,
+        commands: &mut CommandBuffer,
+    ) {
+        if !memory.arrived
+            && DistanceAlg::Pythagoras.distance2d(s.pos, memory.pos) >= MELEE_DISTANCE
+            && let Some(next) = self.path_step(s.pos, memory.pos)
+        {
+            self.step(s, next, commands);
+            return;
+        }
+        if memory.search_turns <= 0 {
+            commands.remove_component::<Memory>(s.entity);
+            return;
+        }
+        commands.add_component(
+            s.entity,
+            Memory {
+                arrived: true,
+                search_turns: memory.search_turns - 1,
+                ..memory
+            },
+        );
+        let exits: Vec<Point> = self
+            .map
+            .get_available_exits(self.map.point2d_to_index(s.pos))
+            .iter()
+            .map(|(exit, _)| self.map.index_to_point2d(*exit))
+            .filter(|exit| DistanceAlg::Pythagoras.distance2d(*exit, memory.pos) <= SEARCH_RADIUS)
+            .collect();
+        if let Some(next) = rng.random_slice_entry(&exits) {
+            self.step(s, *next, commands);
+        }
+    }
+
+    fn go_home(&mut self, s: &Situation, commands: &mut CommandBuffer) {
+        if let Some(next) = self.path_step(s.pos, s.home) {
+            self.step(s, next, commands);
+        }
+    }
+
+    /** 太近时后退，无路可退就近战；在射程内发射投射物；太远时逼近 */
+    fn shoot(&mut self, s: &Situation, range: i32, commands: &mut CommandBuffer) {
+        #[allow(clippy::cast_precision_loss)]
+        let in_range = s.player_distance <= range as f32;
+        if s.player_distance < MIN_SHOOT_DISTANCE {
+            let idx = self.map.point2d_to_index(s.pos);
+            let map = self.map;
+            let chase_map = self.flow_fields.toward_player(map, self.player_pos);
+            let retreat = DijkstraMap::find_highest_exit(chase_map, idx, map)
+                .filter(|exit| chase_map.map[*exit] > chase_map.map[idx])
+                .map(|exit| map.index_to_point2d(exit))
+                .filter(|exit| self.is_free(*exit));
+            match retreat {
+                Some(next) => self.step(s, next, commands),
+                None if s.player_distance < MELEE_DISTANCE => self.attack(s, commands),
+                None => {}
+            }
+        } else if in_range {
+            let path: Vec<Point> = line2d_bresenham(s.pos, self.player_pos)
+                .into_iter()
+                .skip(1)
+                .collect();
+            commands.push((
+                s.pos,
+                Render {
+                    color: ColorPair::new(ORANGE, BLACK),
+                    glyph: to_cp437('*'),
+                },
+                Projectile {
+                    shooter: s.entity,
+                    path,
+                },
+            ));
+        } else if let Some(next) = self.downhill(s.pos) {
+            self.step(s, next, commands);
+        }
+    }
+
+    /** 沿逃跑地图下坡走，无路可逃时只能反击 */
+    fn flee(&mut self, s: &Situation, commands: &mut CommandBuffer) {
+        let idx = self.map.point2d_to_index(s.pos);
+        let map = self.map;
+        let flee_map = self.flow_fields.away_from_player(map, self.player_pos);
+        let exit = DijkstraMap::find_lowest_exit(flee_map, idx, map)
+            .filter(|exit| flee_map.map[*exit] < flee_map.map[idx])
+            .map(|exit| map.index_to_point2d(exit))
+            .filter(|exit| self.is_free(*exit));
+        match exit {
+            Some(next) => self.step(s, next, commands),
+            None if s.player_distance < MELEE_DISTANCE => self.attack(s, commands),
+            None => {}
+        }
+    }
+
+    /** 走向相邻的一格：玩家在那里就攻击，被其他实体占着就原地不动 */
+    fn step(&mut self, s: &Situation, destination: Point, commands: &mut CommandBuffer) {
+        if destination == self.player_pos {
+            self.attack(s, commands);
+        } else if self.is_free(destination) {
+            self.claimed.insert(destination);
+            self.vacated.insert(s.pos);
+            commands.push((
+                (),
+                WantsToMove {
+                    entity: s.entity,
+                    destination,
+                },
+            ));
+        }
+    }
+
+    fn is_free(&self, pos: Point) -> bool {
+        (self.index.blocker_at(pos).is_none() || self.vacated.contains(&pos))
+            && !self.claimed.contains(&pos)
+    }
+
+    fn attack(&self, s: &Situation, commands: &mut CommandBuffer) {
+        commands.push((
+            (),
+            WantsToAttack {
+                attacker: s.entity,
+                victim: self.player,
+            },
+        ));
+    }
+
+    /** 沿追击地图朝玩家走一步 */
+    fn downhill(&mut self, pos: Point) -> Option<Point> {
+        let map = self.map;
+        let idx = map.point2d_to_index(pos);
+        let chase_map = self.flow_fields.toward_player(map, self.player_pos);
+        DijkstraMap::find_lowest_exit(chase_map, idx, map).map(|exit| map.index_to_point2d(exit))
+    }
+
+    /** 朝任意目标的最短路径上的下一格 */
+    fn path_step(&self, from: Point, to: Point) -> Option<Point> {
+        let path = a_star_search(
+            self.map.point2d_to_index(from),
+            self.map.point2d_to_index(to),
+            self.map,
+        );
+        (path.success && path.steps.len() > 1).then(|| self.map.index_to_point2d(path.steps[1]))
+    }
+
+    #[allow(clippy::cast_precision_loss)]
+    fn allies_in(&self, s: &Situation, radius: i32) -> impl Iterator<Item = Entity> + '_ {
+        let (entity, pos) = (s.entity, s.pos);
+        self.unaware
+            .iter()
+            .filter(move |(ally, ally_pos)| {
+                *ally != entity
+                    && DistanceAlg::Pythagoras.distance2d(pos, *ally_pos) <= radius as f32
+            })
+            .map(|(ally, _)| *ally)
+    }
+}
+
+/** 刚看到或听到玩家时的记忆 */
+fn remember(pos: Point) -> Memory {
+    Memory {
+        pos,
+        arrived: false,
+        search_turns: SEARCH_TURNS,
+    }
+}
+
+#[allow(clippy::cast_precision_loss)]
+fn near(center: Point, pos: Point, radius: i32) -> bool {
+    DistanceAlg::Pythagoras.distance2d(center, pos) <= radius as f32
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /** 20×20的空地图，玩家在(10, 10) */
+    struct Fixture {
+        ecs: World,
+        map: Map,
+        index: SpatialIndex,
+        flow_fields: FlowFields,
+        player: Entity,
+    }
+
+    impl Fixture {
+        fn new() -> Self {
+            let mut ecs = World::default();
+            let player = ecs.push((Point::new(10, 10),));
+            let map = Map::new(20, 20);
+            let index = SpatialIndex::build(&ecs, &map);
+            Self {
+                ecs,
+                map,
+                index,
+                flow_fields: FlowFields::default(),
+                player,
+            }
+        }
+
+        fn planner(&mut self) -> Planner<'_> {
+            Planner {
+                map: &self.map,
+                player: self.player,
+                player_pos: Point::new(10, 10),
+                index: &self.index,
+                claimed: HashSet::new(),
+                vacated: HashSet::new(),
+                unaware: Vec::new(),
+                used_potions: HashSet::new(),
+                flow_fields: &mut self.flow_fields,
+            }
+        }
+
+        /** 站在`pos`、能看到玩家的怪物 */
+        fn situation(&mut self, pos: Point, hp: i32) -> Situation {
+            Situation {
+                entity: self.ecs.push((pos,)),
+                pos,
+                health: Health {
+                    current: hp,
+                    max: 10,
+                },
+                home: pos,
+                memory: None,
+                sees_player: true,
+                player_distance: DistanceAlg::Pythagoras.distance2d(pos, Point::new(10, 10)),
+                potion: None,
+            }
+        }
+    }
+
+    fn brain(behaviors: &[(Behavior, f32)]) -> Brain {
+        Brain {
+            behaviors: behaviors.to_vec(),
+            home: Point::zero(),
+        }
+    }
+
+    #[test]
+    fn flee_beats_shoot_when_hurt() {
+        let mut f = Fixture::new();
+        let goblin = brain(&[
+            (Behavior::Shoot { range: 4 }, 1.0),
+            (Behavior::Flee { below: 3 }, 1.0),
+        ]);
+        let healthy = f.situation(Point::new(13, 10), 10);
+        let hurt = f.situation(Point::new(13, 10), 3);
+        let planner = f.planner();
+        assert_eq!(
+            planner.choose(&goblin, &healthy),
+            Some(Behavior::Shoot { range: 4 })
+        );
+        assert_eq!(
+            planner.choose(&goblin, &hurt),
+            Some(Behavior::Flee { below: 3 })
+        );
+    }
+
+    #[test]
+    fn guard_only_chases_inside_its_radius() {
+        let mut f = Fixture::new();
+        let guard = Behavior::Guard { radius: 4 };
+        let mut near_home = f.situation(Point::new(12, 10), 10);
+        near_home.home = Point::new(13, 10);
+        let mut far_home = f.situation(Point::new(12, 10), 10);
+        far_home.home = Point::new(18, 18);
+        let remembered_far = Situation {
+            sees_player: false,
+            memory: Some(remember(Point::new(10, 10))),
+            ..far_home
+        };
+        let planner = f.planner();
+        let inside = planner.score(guard, &near_home);
+        let outside = planner.score(guard, &far_home);
+        //范围外只会回到出生点，得分低于追击
+        assert!(inside > outside, "{inside} <= {outside}");
+        assert!(
+            (planner.score(guard, &remembered_far) - outside).abs() < f32::EPSILON,
+            "remembering a player outside the radius does not make the guard chase"
+        );
+        let orc = brain(&[(guard, 1.0), (Behavior::Wander, 3.0)]);
+        assert_eq!(planner.choose(&orc, &near_home), Some(guard));
+        assert_eq!(planner.choose(&orc, &far_home), Some(Behavior::Wander));
+    }
+
+    #[test]
+    fn use_item_scores_zero_at_full_health() {
+        let mut f = Fixture::new();
+        let potion = f.ecs.push((Point::new(5, 5),));
+        let mut healthy = f.situation(Point::new(4, 4), 10);
+        healthy.potion = Some((potion, Point::new(5, 5)));
+        let hurt = Situation {
+            health: Health {
+                current: 2,
+                max: 10,
+            },
+            ..healthy
+        };
+        let no_potion = Situation {
+            potion: None,
+            ..hurt
+        };
+        let planner = f.planner();
+        assert!(planner.score(Behavior::UseItem, &healthy).abs() < f32::EPSILON);
+        assert!(planner.score(Behavior::UseItem, &hurt) > 0.5);
+        assert!(planner.score(Behavior::UseItem, &no_potion).abs() < f32::EPSILON);
+    }
+
+    #[test]
+    fn weights_change_the_chosen_behavior() {
+        let mut f = Fixture::new();
+        let s = f.situation(Point::new(14, 10), 10);
+        let planner = f.planner();
+        let eager = brain(&[(Behavior::Wander, 1.0), (Behavior::Chase, 1.0)]);
+        assert_eq!(planner.choose(&eager, &s), Some(Behavior::Chase));
+        let lazy = brain(&[(Behavior::Wander, 1.0), (Behavior::Chase, 0.1)]);
+        assert_eq!(planner.choose(&lazy, &s), Some(Behavior::Wander));
+        //得分相同时选排在前面的行为
+        let tied = brain(&[
+            (Behavior::Shoot { range: 8 }, 1.0),
+            (Behavior::Shoot { range: 4 }, 1.0),
+        ]);
+        assert_eq!(planner.choose(&tied, &s), Some(Behavior::Shoot { range: 8 }));
+        assert_eq!(planner.choose(&brain(&[]), &s), None);
+    }
+}
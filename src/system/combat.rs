@@ -1,33 +1,92 @@
 use crate::prelude::*;
 
+/** 战斗声能传多远，范围内的怪物会过来查看 */
+const NOISE_RADIUS: i32 = 6;
+
 #[system]
 #[read_component(WantsToAttack)]
+#[read_component(Point)]
 #[read_component(Player)]
+#[read_component(Damage)]
+#[read_component(Armor)]
+#[read_component(Carried)]
+#[read_component(Equipped)]
 #[write_component(Health)]
-pub fn combat(ecs: &mut SubWorld, commands: &mut CommandBuffer) {
+pub fn combat(
+    ecs: &mut SubWorld,
+    commands: &mut CommandBuffer,
+    #[resource] rng: &mut RandomNumberGenerator,
+    #[resource] index: &mut SpatialIndex,
+) {
     let mut attacker = <(Entity, &WantsToAttack)>::query();
-    let victims: Vec<(Entity, Entity)> = attacker
+    let victims: Vec<(Entity, Entity, Entity)> = attacker
         .iter(ecs)
-        .map(|(entity, attack)| (*entity, attack.victim))
+        .map(|(entity, attack)| (*entity, attack.attacker, attack.victim))
         .collect();
-    victims.iter().for_each(|(message, victim)| {
-        let is_player = ecs
-            .entry_ref(*victim)
-            .unwrap()
-            .get_component::<Player>()
-            .is_ok();
+    for (message, attacker, victim) in &victims {
+        //投射物和范围伤害的目标可能已经被移除
+        let Ok(victim_entry) = ecs.entry_ref(*victim) else {
+            commands.remove(*message);
+            continue;
+        };
+        let damage = i32::max(
+            0,
+            roll_damage(ecs, rng, *attacker) - total_armor(ecs, *victim),
+        );
+        let is_player = victim_entry.get_component::<Player>().is_ok();
+        let victim_pos = victim_entry.get_component::<Point>().ok().copied();
+        if let Some(pos) = victim_pos {
+            commands.push((
+                (),
+                Noise {
+                    pos,
+                    radius: NOISE_RADIUS,
+                },
+            ));
+        }
         if let Ok(health) = ecs
             .entry_mut(*victim)
             .unwrap()
             .get_component_mut::<Health>()
         {
-            println!("health before attack: {}", health.current);
-            health.current -= 1;
+            health.current -= damage;
             if health.current < 1 && !is_player {
                 commands.remove(*victim);
+                if let Some(pos) = victim_pos {
+                    index.remove(*victim, pos);
+                }
             }
-            println!("health after attack: {}", health.current);
         }
         commands.remove(*message);
+    }
+}
+
+/** 攻击者自身的伤害骰加上已装备武器的伤害骰，没有攻击力组件的实体默认造成1点伤害 */
+fn roll_damage(ecs: &SubWorld, rng: &mut RandomNumberGenerator, attacker: Entity) -> i32 {
+    let base_damage = ecs.entry_ref(attacker).map_or(1, |entry| {
+        entry
+            .get_component::<Damage>()
+            .map_or(1, |damage| rng.roll(damage.0))
     });
+    let weapon_damage: i32 = <(&Carried, &Damage)>::query()
+        .filter(component::<Equipped>())
+        .iter(ecs)
+        .filter(|(carried, _)| carried.0 == attacker)
+        .map(|(_, damage)| rng.roll(damage.0))
+        .sum();
+    base_damage + weapon_damage
+}
+
+/** 受害者自身的护甲加上已装备物品提供的护甲 */
+fn total_armor(ecs: &SubWorld, victim: Entity) -> i32 {
+    let base_armor = ecs.entry_ref(victim).map_or(0, |entry| {
+        entry.get_component::<Armor>().map_or(0, |armor| armor.0)
+    });
+    let equipped_armor: i32 = <(&Carried, &Armor)>::query()
+        .filter(component::<Equipped>())
+        .iter(ecs)
+        .filter(|(carried, _)| carried.0 == victim)
+        .map(|(_, armor)| armor.0)
+        .sum();
+    base_armor + equipped_armor
 }
@@ -5,11 +5,13 @@ use crate::prelude::*;
 #[read_component(Point)]
 #[read_component(Player)]
 #[read_component(AmuletOfYala)]
-pub fn end_turn(ecs: &SubWorld, #[resource] turn_state: &mut TurnState) {
+pub fn end_turn(ecs: &SubWorld, #[resource] turn_state: &mut TurnState, #[resource] map: &Map) {
     let mut player_hp = <(&Health, &Point)>::query().filter(component::<Player>());
     let mut amulet = <&Point>::query().filter(component::<AmuletOfYala>());
-    let amulet_pos = amulet.iter(ecs).nth(0).unwrap();
-    let current_state = turn_state.clone();
+    //只有最深一层才有护身符，其余层用一个不可能到达的坐标代替
+    let amulet_default = Point::new(-1, -1);
+    let amulet_pos = amulet.iter(ecs).next().unwrap_or(&amulet_default);
+    let current_state = *turn_state;
     let mut new_state = match current_state {
         TurnState::AwaitingInput => return,
         TurnState::PlayerTurn => TurnState::MonsterTurn,
@@ -17,12 +19,16 @@ pub fn end_turn(ecs: &SubWorld, #[resource] turn_state: &mut TurnState) {
         _ => current_state,
     };
     player_hp.iter(ecs).for_each(|(hp, pos)| {
-        if hp.current < 1 {
-            new_state = TurnState::GameOver;
+        let idx = map.point2d_to_index(*pos);
+        if map.tiles[idx] == TileType::Exit {
+            new_state = TurnState::NextLevel;
         }
         if pos == amulet_pos {
             new_state = TurnState::Victory;
         }
+        if hp.current < 1 {
+            new_state = TurnState::GameOver;
+        }
     });
     *turn_state = new_state;
 }
@@ -5,10 +5,10 @@ use crate::prelude::*;
 #[read_component(Player)]
 pub fn hud(ecs: &SubWorld) {
     let mut health_query = <&Health>::query().filter(component::<Player>());
-    let player_health = health_query.iter(ecs).nth(0).unwrap();
+    let player_health = health_query.iter(ecs).next().unwrap();
     let mut draw_batch = DrawBatch::new();
     draw_batch.target(2);
-    draw_batch.print_centered(1, "Explore the Dungeon.Cursor keys to move.");
+    draw_batch.print_centered(1, "Explore the Dungeon.Cursor keys to move, G to pick up, I for inventory.");
     draw_batch.bar_horizontal(
         Point::zero(),
         SCREEN_WIDTH * 2,
@@ -21,5 +21,12 @@ pub fn hud(ecs: &SubWorld) {
         format!("Health: {} / {}", player_health.current, player_health.max),
         ColorPair::new(WHITE, RED),
     );
+    let mut depth_query = <&Player>::query();
+    let depth = depth_query.iter(ecs).next().unwrap().depth;
+    draw_batch.print_color_right(
+        Point::new(SCREEN_WIDTH * 2, 1),
+        format!("Depth: {depth} / {DUNGEON_DEPTH}"),
+        ColorPair::new(YELLOW, BLACK),
+    );
     draw_batch.submit(10000).expect("Batch Error");
 }
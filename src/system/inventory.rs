@@ -0,0 +1,51 @@
+use crate::prelude::*;
+
+#[system]
+#[allow(clippy::ref_option, clippy::trivially_copy_pass_by_ref)]
+#[read_component(Item)]
+#[read_component(Carried)]
+#[read_component(Name)]
+#[read_component(Player)]
+pub fn inventory(
+    ecs: &SubWorld,
+    commands: &mut CommandBuffer,
+    #[resource] key: &Option<VirtualKeyCode>,
+    #[resource] turn_state: &mut TurnState,
+) {
+    let player = *<Entity>::query()
+        .filter(component::<Player>())
+        .iter(ecs)
+        .next()
+        .unwrap();
+    let carried = carried_items(ecs, player);
+
+    if let Some(key) = key {
+        if *key == VirtualKeyCode::Escape {
+            *turn_state = TurnState::AwaitingInput;
+        } else if let Ok(selection) = usize::try_from(letter_to_option(*key))
+            && let Some((item, _)) = carried.get(selection)
+        {
+            commands.push((
+                (),
+                WantsToUseItem {
+                    used_by: player,
+                    item: *item,
+                },
+            ));
+            *turn_state = TurnState::PlayerTurn;
+        }
+    }
+}
+
+/** 玩家携带的物品及其名称，背包的输入和绘制共用同一顺序 */
+pub(super) fn carried_items(ecs: &SubWorld, player: Entity) -> Vec<(Entity, String)> {
+    let mut carried: Vec<(Entity, String)> = <(Entity, &Carried, &Name)>::query()
+        .filter(component::<Item>())
+        .iter(ecs)
+        .filter(|(_, carried, _)| carried.0 == player)
+        .map(|(entity, _, name)| (*entity, name.0.clone()))
+        .collect();
+    //按不带装备标记的名称排序，装备或卸下物品时字母快捷键对应的物品不变
+    carried.sort_by(|a, b| a.1.cmp(&b.1));
+    carried
+}
@@ -0,0 +1,49 @@
+use crate::prelude::*;
+
+#[system]
+#[read_component(Item)]
+#[read_component(Carried)]
+#[read_component(Name)]
+#[read_component(Equipped)]
+#[read_component(Player)]
+pub fn inventory_render(ecs: &SubWorld) {
+    let player = *<Entity>::query()
+        .filter(component::<Player>())
+        .iter(ecs)
+        .next()
+        .unwrap();
+    let carried = super::inventory::carried_items(ecs, player);
+
+    let mut draw_batch = DrawBatch::new();
+    draw_batch.target(2);
+    let height = i32::try_from(carried.len()).unwrap_or(0) + 4;
+    let top = 4;
+    draw_batch.draw_double_box(
+        Rect::with_size(SCREEN_WIDTH / 2, top, SCREEN_WIDTH, height),
+        ColorPair::new(WHITE, BLACK),
+    );
+    draw_batch.print_color(
+        Point::new(SCREEN_WIDTH / 2 + 2, top),
+        "Inventory",
+        ColorPair::new(YELLOW, BLACK),
+    );
+    if carried.is_empty() {
+        draw_batch.print(Point::new(SCREEN_WIDTH / 2 + 2, top + 2), "You carry nothing.");
+    }
+    for (y, (hotkey, (item, name))) in (top + 2..).zip(('a'..='z').zip(carried.iter())) {
+        let equipped = ecs
+            .entry_ref(*item)
+            .is_ok_and(|entry| entry.get_component::<Equipped>().is_ok());
+        let suffix = if equipped { " (equipped)" } else { "" };
+        draw_batch.print(
+            Point::new(SCREEN_WIDTH / 2 + 2, y),
+            format!("{hotkey}) {name}{suffix}"),
+        );
+    }
+    draw_batch.print_color(
+        Point::new(SCREEN_WIDTH / 2 + 2, top + height),
+        "Letter: use item    Esc: close",
+        ColorPair::new(GRAY, BLACK),
+    );
+    draw_batch.submit(11000).expect("Batch Error");
+}
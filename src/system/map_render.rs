@@ -1,30 +1,34 @@
 use crate::prelude::*;
 
 #[system]
+#[allow(clippy::borrowed_box)]
 #[read_component(Player)]
 #[read_component(FeildOfView)]
-pub fn map_render(ecs: &SubWorld, #[resource] map: &Map, #[resource] camera: &Camera) {
+pub fn map_render(
+    ecs: &SubWorld,
+    #[resource] map: &Map,
+    #[resource] camera: &Camera,
+    #[resource] theme: &Box<dyn MapTheme>,
+) {
     let mut fov = <&FeildOfView>::query().filter(component::<Player>());
-    let player_fov = fov.iter(ecs).nth(0).unwrap();
+    let player_fov = fov.iter(ecs).next().unwrap();
     let mut draw_batch = DrawBatch::new();
     draw_batch.target(0);
     for y in camera.top_y..=camera.bottom_y {
         for x in camera.left_x..=camera.right_x {
             let pt = Point::new(x, y);
             let offset = Point::new(camera.left_x, camera.top_y);
-            let idx = map_idx(x, y);
+            let idx = map.idx(x, y);
             if map.in_bounds(pt)
                 && (player_fov.visible_tiles.contains(&pt) || map.revealed_tiles[idx])
             {
+                let tile = map.tiles[idx];
                 let tint = if player_fov.visible_tiles.contains(&pt) {
-                    WHITE
+                    theme.tile_color(tile)
                 } else {
-                    DARK_GRAY
-                };
-                let glyph = match map.tiles[idx] {
-                    TileType::Floor => to_cp437('.'),
-                    TileType::Wall => to_cp437('#'),
+                    theme.remembered_color(tile)
                 };
+                let glyph = theme.tile_to_render(tile);
                 draw_batch.set(pt - offset, ColorPair::new(tint, BLACK), glyph);
             }
         }
@@ -6,54 +6,82 @@ mod hud;
 mod map_render;
 mod movement;
 mod player_input;
-mod random_move;
 mod tooltips;
-mod chasing;
+mod ai;
+mod projectile;
 mod field_of_view;
+mod inventory;
+mod inventory_render;
+mod pickup;
+mod use_items;
 
 use crate::prelude::*;
+use legion::systems::Builder;
 
-pub fn build_input_schedule() -> Schedule {
-    Schedule::builder()
-        .add_system(player_input::player_input_system())
-        .add_system(field_of_view::field_of_view_system())
-        .flush()
+/** 绘制地图、实体和界面的系统，无窗口模式下不加入调度 */
+fn add_render_systems(builder: &mut Builder) {
+    builder
         .add_system(map_render::map_render_system())
         .add_system(entity_render::entity_render_system())
-        .add_system(hud::hud_system())
-        .add_system(tooltips::tooltips_system())
-        .build()
+        .add_system(hud::hud_system());
+}
+
+pub fn build_input_schedule(render: bool) -> Schedule {
+    let mut builder = Schedule::builder();
+    builder
+        .add_system(player_input::player_input_system())
+        .add_system(field_of_view::field_of_view_system())
+        .flush();
+    if render {
+        add_render_systems(&mut builder);
+        builder.add_system(tooltips::tooltips_system());
+    }
+    builder.build()
+}
+
+pub fn build_inventory_schedule(render: bool) -> Schedule {
+    let mut builder = Schedule::builder();
+    builder.add_system(inventory::inventory_system()).flush();
+    if render {
+        add_render_systems(&mut builder);
+        builder.add_system(inventory_render::inventory_render_system());
+    }
+    builder.build()
 }
 
-pub fn build_player_schedule() -> Schedule {
-    Schedule::builder()
+pub fn build_player_schedule(render: bool) -> Schedule {
+    let mut builder = Schedule::builder();
+    builder
+        .add_system(pickup::pickup_system())
+        .add_system(use_items::use_items_system())
         .add_system(combat::combat_system())
         .flush()
         .add_system(movement::movement_system())
         .flush()
         .add_system(field_of_view::field_of_view_system())
-        .flush()
-        .add_system(map_render::map_render_system())
-        .add_system(entity_render::entity_render_system())
-        .add_system(hud::hud_system())
-        .add_system(end_turn::end_turn_system())
-        .build()
+        .flush();
+    if render {
+        add_render_systems(&mut builder);
+    }
+    builder.add_system(end_turn::end_turn_system()).build()
 }
 
-pub fn build_monster_schedule() -> Schedule {
-    Schedule::builder()
-        .add_system(random_move::random_move_system())
-        .add_system(chasing::chasing_system())
+pub fn build_monster_schedule(render: bool) -> Schedule {
+    let mut builder = Schedule::builder();
+    builder
+        .add_system(ai::ai_system())
         .flush()
+        .add_system(projectile::projectile_system())
+        .flush()
+        .add_system(use_items::use_items_system())
         .add_system(combat::combat_system())
         .flush()
         .add_system(movement::movement_system())
         .flush()
         .add_system(field_of_view::field_of_view_system())
-        .flush()
-        .add_system(map_render::map_render_system())
-        .add_system(entity_render::entity_render_system())
-        .add_system(hud::hud_system())
-        .add_system(end_turn::end_turn_system())
-        .build()
+        .flush();
+    if render {
+        add_render_systems(&mut builder);
+    }
+    builder.add_system(end_turn::end_turn_system()).build()
 }
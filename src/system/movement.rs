@@ -1,29 +1,201 @@
 use crate::prelude::*;
 
 #[system(for_each)]
+#[allow(clippy::trivially_copy_pass_by_ref)]
 #[read_component(Player)]
 #[read_component(FeildOfView)]
+#[read_component(Point)]
+#[write_component(Health)]
 pub fn movement(
     entity: &Entity,
     want_move: &WantsToMove,
     #[resource] map: &mut Map,
     #[resource] camera: &mut Camera,
+    #[resource] index: &mut SpatialIndex,
     ecs: &mut SubWorld,
     commands: &mut CommandBuffer,
 ) {
-    if map.can_enter_tile(want_move.destination) {
+    //被流弹打死的怪物留下的移动请求没有位置
+    let from = ecs
+        .entry_ref(want_move.entity)
+        .ok()
+        .and_then(|entry| entry.get_component::<Point>().ok().copied());
+    //不能走进被其他实体挡住的地块
+    let blocked = index
+        .blocker_at(want_move.destination)
+        .is_some_and(|blocker| blocker != want_move.entity);
+    if let Some(from) = from
+        && !blocked
+        && map.can_enter_tile(want_move.destination)
+    {
         commands.add_component(want_move.entity, want_move.destination);
-        if let Ok(entry) = ecs.entry_ref(want_move.entity) {
-            if let Ok(fov) = entry.get_component::<FeildOfView>() {
-                commands.add_component(want_move.entity, fov.clone_dirty());
-                if entry.get_component::<Player>().is_ok() {
-                    camera.on_player_move(want_move.destination);
-                    fov.visible_tiles.iter().for_each(|pos| {
-                        map.revealed_tiles[map_idx(pos.x, pos.y)] = true;
-                    })
-                }
+        index.move_entity(want_move.entity, from, want_move.destination);
+        let idx = map.idx(want_move.destination.x, want_move.destination.y);
+        //走进关着的门会把门打开
+        if map.tiles[idx] == TileType::DoorClosed {
+            map.set_tile(idx, TileType::DoorOpen);
+        }
+        let damage = map.tiles[idx].damage();
+        let mut burned = false;
+        if damage > 0
+            && let Ok(mut entry) = ecs.entry_mut(want_move.entity)
+            && let Ok(health) = entry.get_component_mut::<Health>()
+        {
+            health.current -= damage;
+            //怪物被地形烧死，玩家的死亡交给end_turn处理
+            burned = health.current < 1 && entry.get_component::<Player>().is_err();
+        }
+        if burned {
+            commands.remove(want_move.entity);
+            index.remove(want_move.entity, want_move.destination);
+        } else if let Ok(entry) = ecs.entry_ref(want_move.entity)
+            && let Ok(fov) = entry.get_component::<FeildOfView>()
+        {
+            commands.add_component(want_move.entity, fov.clone_dirty());
+            if entry.get_component::<Player>().is_ok() {
+                camera.on_player_move(want_move.destination);
+                fov.visible_tiles.iter().for_each(|pos| {
+                    let idx = map.idx(pos.x, pos.y);
+                    map.revealed_tiles[idx] = true;
+                });
             }
         }
     }
     commands.remove(*entity);
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /** 在给定的小地图上执行移动系统 */
+    struct Harness {
+        ecs: World,
+        resources: Resources,
+        schedule: Schedule,
+    }
+
+    impl Harness {
+        fn new(map: Map) -> Self {
+            let mut resources = Resources::default();
+            resources.insert(Camera::new(Point::new(1, 1), &map));
+            resources.insert(map);
+            Self {
+                ecs: World::default(),
+                resources,
+                schedule: Schedule::builder()
+                    .add_system(movement_system())
+                    .flush()
+                    .build(),
+            }
+        }
+
+        fn spawn(&mut self, pos: Point, hp: i32) -> Entity {
+            self.ecs.push((
+                pos,
+                Health {
+                    current: hp,
+                    max: hp,
+                },
+                FeildOfView::new(6),
+            ))
+        }
+
+        /** 所有实体就位后再构建空间索引，然后执行一次移动 */
+        fn run(&mut self, moves: &[(Entity, Point)]) {
+            let index = SpatialIndex::build(&self.ecs, &self.resources.get::<Map>().unwrap());
+            self.resources.insert(index);
+            for (entity, destination) in moves {
+                self.ecs.push((
+                    (),
+                    WantsToMove {
+                        entity: *entity,
+                        destination: *destination,
+                    },
+                ));
+            }
+            self.schedule.execute(&mut self.ecs, &mut self.resources);
+        }
+
+        fn pos(&self, entity: Entity) -> Option<Point> {
+            self.ecs
+                .entry_ref(entity)
+                .ok()
+                .and_then(|entry| entry.get_component::<Point>().ok().copied())
+        }
+    }
+
+    #[test]
+    fn rejects_move_into_occupied_tile() {
+        let mut h = Harness::new(Map::new(8, 8));
+        let player = h.spawn(Point::new(2, 2), 10);
+        let monster = h.spawn(Point::new(3, 2), 10);
+        h.run(&[(player, Point::new(3, 2))]);
+        assert_eq!(h.pos(player), Some(Point::new(2, 2)));
+        assert_eq!(h.pos(monster), Some(Point::new(3, 2)));
+        let index = h.resources.get::<SpatialIndex>().unwrap();
+        assert_eq!(index.blocker_at(Point::new(3, 2)), Some(monster));
+        assert_eq!(index.blocker_at(Point::new(2, 2)), Some(player));
+    }
+
+    #[test]
+    fn moves_into_free_tile_and_updates_index() {
+        let mut h = Harness::new(Map::new(8, 8));
+        let monster = h.spawn(Point::new(3, 2), 10);
+        h.run(&[(monster, Point::new(3, 3))]);
+        assert_eq!(h.pos(monster), Some(Point::new(3, 3)));
+        let index = h.resources.get::<SpatialIndex>().unwrap();
+        assert_eq!(index.blocker_at(Point::new(3, 2)), None);
+        assert_eq!(index.blocker_at(Point::new(3, 3)), Some(monster));
+    }
+
+    #[test]
+    fn walking_into_a_closed_door_opens_it() {
+        let mut map = Map::new(8, 8);
+        let door = map.idx(3, 2);
+        map.tiles[door] = TileType::DoorClosed;
+        let mut h = Harness::new(map);
+        let revision = h.resources.get::<Map>().unwrap().revision();
+        let monster = h.spawn(Point::new(2, 2), 10);
+        h.run(&[(monster, Point::new(3, 2))]);
+        assert_eq!(h.pos(monster), Some(Point::new(3, 2)));
+        let map = h.resources.get::<Map>().unwrap();
+        assert_eq!(map.tiles[door], TileType::DoorOpen);
+        assert_ne!(map.revision(), revision, "opening a door changes the terrain");
+    }
+
+    #[test]
+    fn lava_burns_whoever_steps_in() {
+        let mut map = Map::new(8, 8);
+        for x in 3..6 {
+            let idx = map.idx(x, 2);
+            map.tiles[idx] = TileType::Lava;
+        }
+        let damage = TileType::Lava.damage();
+        let mut h = Harness::new(map);
+        let tough = h.spawn(Point::new(3, 1), damage + 1);
+        let weak = h.spawn(Point::new(4, 1), damage);
+        let player = h.spawn(Point::new(5, 1), damage);
+        h.ecs.entry(player).unwrap().add_component(Player { depth: 1 });
+        h.run(&[
+            (tough, Point::new(3, 2)),
+            (weak, Point::new(4, 2)),
+            (player, Point::new(5, 2)),
+        ]);
+
+        let health = |h: &Harness, entity: Entity| {
+            h.ecs
+                .entry_ref(entity)
+                .ok()
+                .and_then(|entry| entry.get_component::<Health>().ok().map(|hp| hp.current))
+        };
+        assert_eq!(health(&h, tough), Some(1));
+        //烧死的怪物被移除，也不再挡路
+        assert!(h.ecs.entry_ref(weak).is_err());
+        let index = h.resources.get::<SpatialIndex>().unwrap();
+        assert_eq!(index.blocker_at(Point::new(4, 2)), None);
+        //玩家的死亡交给end_turn处理
+        assert_eq!(health(&h, player), Some(0));
+        assert_eq!(h.pos(player), Some(Point::new(5, 2)));
+    }
+}
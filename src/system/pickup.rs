@@ -0,0 +1,22 @@
+use crate::prelude::*;
+
+#[system(for_each)]
+#[allow(clippy::trivially_copy_pass_by_ref)]
+#[read_component(Point)]
+pub fn pickup(
+    entity: &Entity,
+    want_pickup: &WantsToPickUp,
+    #[resource] index: &mut SpatialIndex,
+    ecs: &SubWorld,
+    commands: &mut CommandBuffer,
+) {
+    //物品离开地图，改为由拾取者携带
+    if let Ok(item) = ecs.entry_ref(want_pickup.item)
+        && let Ok(pos) = item.get_component::<Point>()
+    {
+        index.remove(want_pickup.item, *pos);
+    }
+    commands.remove_component::<Point>(want_pickup.item);
+    commands.add_component(want_pickup.item, Carried(want_pickup.collector));
+    commands.remove(*entity);
+}
@@ -1,52 +1,82 @@
 use crate::prelude::*;
 
 #[system]
+#[allow(clippy::ref_option, clippy::trivially_copy_pass_by_ref)]
 #[write_component(Point)]
 #[read_component(Player)]
 #[read_component(Enemy)]
+#[read_component(Item)]
+#[read_component(AmuletOfYala)]
 #[write_component(Health)]
 pub fn player_input(
     ecs: &mut SubWorld,
     commands: &mut CommandBuffer,
     #[resource] key: &Option<VirtualKeyCode>,
     #[resource] turn_state: &mut TurnState,
+    #[resource] index: &SpatialIndex,
 ) {
     let mut players = <(Entity, &Point)>::query().filter(component::<Player>());
     if let Some(key) = key {
+        let mut did_something = false;
         let delta = match key {
-            VirtualKeyCode::Left => Point::new(-1, 0),
-            VirtualKeyCode::A => Point::new(-1, 0),
-            VirtualKeyCode::Right => Point::new(1, 0),
-            VirtualKeyCode::D => Point::new(1, 0),
-            VirtualKeyCode::Up => Point::new(0, -1),
-            VirtualKeyCode::W => Point::new(0, -1),
-            VirtualKeyCode::Down => Point::new(0, 1),
-            VirtualKeyCode::S => Point::new(0, 1),
+            VirtualKeyCode::Left | VirtualKeyCode::A => Point::new(-1, 0),
+            VirtualKeyCode::Right | VirtualKeyCode::D => Point::new(1, 0),
+            VirtualKeyCode::Up | VirtualKeyCode::W => Point::new(0, -1),
+            VirtualKeyCode::Down | VirtualKeyCode::S => Point::new(0, 1),
+            VirtualKeyCode::G => {
+                //捡起脚下的物品，护身符不需要捡，踩上去就胜利了
+                let (player, player_pos) = players
+                    .iter(ecs)
+                    .map(|(entity, pos)| (*entity, *pos))
+                    .next()
+                    .unwrap();
+                index
+                    .entities_at(player_pos)
+                    .iter()
+                    .filter(|entity| {
+                        ecs.entry_ref(**entity).is_ok_and(|entry| {
+                            entry.get_component::<Item>().is_ok()
+                                && entry.get_component::<AmuletOfYala>().is_err()
+                        })
+                    })
+                    .for_each(|entity| {
+                        did_something = true;
+                        commands.push((
+                            (),
+                            WantsToPickUp {
+                                collector: player,
+                                item: *entity,
+                            },
+                        ));
+                    });
+                Point::zero()
+            }
+            VirtualKeyCode::I => {
+                *turn_state = TurnState::ShowingInventory;
+                return;
+            }
             _ => Point::zero(),
         };
         let (player_entity, destination) = players
             .iter(ecs)
-            .find_map(|(entity, pos)| Some((*entity, *pos + delta)))
+            .map(|(entity, pos)| (*entity, *pos + delta))
+            .next()
             .unwrap();
-        let mut enemies = <(Entity, &Point)>::query().filter(component::<Enemy>());
-        let mut did_something = false;
         if delta.x != 0 || delta.y != 0 {
-            let mut hit_something = false;
-            enemies
-                .iter(ecs)
-                .filter(|(_, pos)| **pos == destination)
-                .for_each(|(entity, _)| {
-                    hit_something = true;
-                    did_something = true;
-                    commands.push((
-                        (),
-                        WantsToAttack {
-                            attacker: player_entity,
-                            victim: *entity,
-                        },
-                    ));
-                });
-            if !hit_something {
+            let enemy = index.blocker_at(destination).filter(|entity| {
+                ecs.entry_ref(*entity)
+                    .is_ok_and(|entry| entry.get_component::<Enemy>().is_ok())
+            });
+            if let Some(enemy) = enemy {
+                did_something = true;
+                commands.push((
+                    (),
+                    WantsToAttack {
+                        attacker: player_entity,
+                        victim: enemy,
+                    },
+                ));
+            } else {
                 did_something = true;
                 commands.push((
                     (),
@@ -57,14 +87,13 @@ pub fn player_input(
                 ));
             }
         }
-        if !did_something {
-            if let Ok(health) = ecs
+        if !did_something
+            && let Ok(health) = ecs
                 .entry_mut(player_entity)
                 .unwrap()
                 .get_component_mut::<Health>()
-            {
-                health.current = i32::min(health.max, health.current + 1);
-            }
+        {
+            health.current = i32::min(health.max, health.current + 1);
         }
         *turn_state = TurnState::PlayerTurn;
     }
@@ -0,0 +1,98 @@
+use crate::prelude::*;
+
+/** 投射物每回合飞行的格数 */
+const PROJECTILE_SPEED: usize = 2;
+
+/** 推进飞行中的投射物。只有怪物会射击，投射物从其他怪物身边飞过，不会误伤同伴 */
+#[system]
+#[read_component(Point)]
+#[read_component(Projectile)]
+#[read_component(Enemy)]
+pub fn projectile(
+    #[resource] map: &Map,
+    #[resource] index: &SpatialIndex,
+    ecs: &SubWorld,
+    commands: &mut CommandBuffer,
+) {
+    let mut projectiles = <(Entity, &Point, &Projectile)>::query();
+    projectiles.iter(ecs).for_each(|(entity, pos, projectile)| {
+        let mut pos = *pos;
+        let mut path = projectile.path.iter();
+        for next in path.by_ref().take(PROJECTILE_SPEED) {
+            //撞到墙或关着的门
+            if map.try_idx(*next).is_none_or(|idx| map.tiles[idx].opaque()) {
+                commands.remove(*entity);
+                return;
+            }
+            //命中路径上第一个不是怪物的实体，伤害交给战斗系统结算
+            if let Some(victim) = index.blocker_at(*next).filter(|victim| {
+                ecs.entry_ref(*victim)
+                    .is_ok_and(|entry| entry.get_component::<Enemy>().is_err())
+            }) {
+                commands.push((
+                    (),
+                    WantsToAttack {
+                        attacker: projectile.shooter,
+                        victim,
+                    },
+                ));
+                commands.remove(*entity);
+                return;
+            }
+            pos = *next;
+        }
+        let remaining: Vec<Point> = path.copied().collect();
+        if remaining.is_empty() {
+            //飞到目标位置也没有命中
+            commands.remove(*entity);
+        } else {
+            commands.add_component(*entity, pos);
+            commands.add_component(
+                *entity,
+                Projectile {
+                    shooter: projectile.shooter,
+                    path: remaining,
+                },
+            );
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /** 射手和玩家之间隔着另一个怪物，投射物飞过怪物命中玩家 */
+    #[test]
+    fn passes_over_other_monsters() {
+        let map = Map::new(10, 3);
+        let mut ecs = World::default();
+        let goblin = ecs.push((Enemy, Point::new(1, 1), Health { current: 1, max: 1 }));
+        let orc = ecs.push((Enemy, Point::new(2, 1), Health { current: 1, max: 1 }));
+        let player = ecs.push((
+            Player { depth: 1 },
+            Point::new(3, 1),
+            Health { current: 1, max: 1 },
+        ));
+        ecs.push((
+            Point::new(1, 1),
+            Projectile {
+                shooter: goblin,
+                path: vec![Point::new(2, 1), Point::new(3, 1), Point::new(4, 1)],
+            },
+        ));
+        let mut resources = Resources::default();
+        resources.insert(SpatialIndex::build(&ecs, &map));
+        resources.insert(map);
+        let mut schedule = Schedule::builder().add_system(projectile_system()).build();
+        schedule.execute(&mut ecs, &mut resources);
+
+        let victims: Vec<Entity> = <&WantsToAttack>::query()
+            .iter(&ecs)
+            .map(|attack| attack.victim)
+            .collect();
+        assert_eq!(victims, vec![player]);
+        assert!(<&Projectile>::query().iter(&ecs).next().is_none());
+        assert!(ecs.entry_ref(orc).is_ok());
+    }
+}
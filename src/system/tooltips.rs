@@ -1,31 +1,40 @@
 use crate::prelude::*;
 
 #[system]
+#[allow(clippy::trivially_copy_pass_by_ref)]
 #[write_component(Point)]
 #[read_component(Name)]
 #[read_component(Health)]
 #[read_component(FeildOfView)]
 #[read_component(Player)]
-pub fn tooltips(ecs: &SubWorld, #[resource] mouse_pos: &Point, #[resource] camera: &Camera) {
-    let mut positions = <(Entity, &Point, &Name)>::query();
+pub fn tooltips(
+    ecs: &SubWorld,
+    #[resource] mouse_pos: &Point,
+    #[resource] camera: &Camera,
+    #[resource] index: &SpatialIndex,
+) {
     let mut fov = <&FeildOfView>::query().filter(component::<Player>());
-    let player_fov = fov.iter(ecs).nth(0).unwrap();
+    let player_fov = fov.iter(ecs).next().unwrap();
     let offset = Point::new(camera.left_x, camera.top_y);
     let map_pos = *mouse_pos + offset;
     let mut draw_batch = DrawBatch::new();
     draw_batch.target(2);
-    positions
-        .iter(ecs)
-        .filter(|(_, pos, _)| **pos == map_pos && player_fov.visible_tiles.contains(pos))
-        .for_each(|(entity, _, name)| {
+    if player_fov.visible_tiles.contains(&map_pos) {
+        for entity in index.entities_at(map_pos) {
+            let Ok(entry) = ecs.entry_ref(*entity) else {
+                continue;
+            };
+            let Ok(name) = entry.get_component::<Name>() else {
+                continue;
+            };
             let screen_pos = *mouse_pos * 4;
-            let display =
-                if let Ok(health) = ecs.entry_ref(*entity).unwrap().get_component::<Health>() {
-                    format!("{}: {}", &name.0, health.current)
-                } else {
-                    name.0.clone()
-                };
+            let display = if let Ok(health) = entry.get_component::<Health>() {
+                format!("{}: {}", &name.0, health.current)
+            } else {
+                name.0.clone()
+            };
             draw_batch.print(screen_pos, &display);
-        });
+        }
+    }
     draw_batch.submit(10100).expect("Batch error");
 }
@@ -0,0 +1,132 @@
+use crate::prelude::*;
+
+#[system]
+#[read_component(WantsToUseItem)]
+#[read_component(ProvidesHealing)]
+#[read_component(ProvidesDungeonMap)]
+#[read_component(ProvidesTeleport)]
+#[read_component(ProvidesAreaDamage)]
+#[read_component(Equippable)]
+#[read_component(Equipped)]
+#[read_component(Carried)]
+#[read_component(Point)]
+#[read_component(Player)]
+#[write_component(Health)]
+pub fn use_items(
+    ecs: &mut SubWorld,
+    commands: &mut CommandBuffer,
+    #[resource] map: &mut Map,
+    #[resource] rng: &mut RandomNumberGenerator,
+    #[resource] index: &mut SpatialIndex,
+) {
+    let mut healing_to_apply = Vec::<(Entity, i32)>::new();
+    let mut teleports = Vec::<Entity>::new();
+    let mut area_damage = Vec::<(Entity, Point, ProvidesAreaDamage)>::new();
+    let mut equips = Vec::<(Entity, Entity, EquipSlot)>::new();
+    <(Entity, &WantsToUseItem)>::query()
+        .iter(ecs)
+        .for_each(|(message, use_item)| {
+            //逐个检查物品身上的效果组件，新的消耗品只需组合已有的效果
+            if let Ok(item) = ecs.entry_ref(use_item.item) {
+                //可装备的物品不会被消耗，而是切换装备状态
+                if let Ok(equippable) = item.get_component::<Equippable>() {
+                    equips.push((use_item.used_by, use_item.item, equippable.0));
+                    commands.remove(*message);
+                    return;
+                }
+                if let Ok(healing) = item.get_component::<ProvidesHealing>() {
+                    healing_to_apply.push((use_item.used_by, healing.amount));
+                }
+                if item.get_component::<ProvidesDungeonMap>().is_ok() {
+                    map.revealed_tiles.iter_mut().for_each(|t| *t = true);
+                }
+                if item.get_component::<ProvidesTeleport>().is_ok() {
+                    teleports.push(use_item.used_by);
+                }
+                if let Ok(area) = item.get_component::<ProvidesAreaDamage>()
+                    && let Ok(user) = ecs.entry_ref(use_item.used_by)
+                    && let Ok(center) = user.get_component::<Point>()
+                {
+                    area_damage.push((use_item.used_by, *center, *area));
+                }
+                //怪物会直接喝掉地上的药水
+                if let Ok(pos) = item.get_component::<Point>() {
+                    index.remove(use_item.item, *pos);
+                }
+            }
+            //物品都是一次性的，使用后即消失
+            commands.remove(use_item.item);
+            commands.remove(*message);
+        });
+
+    for (user, item, slot) in equips {
+        let already_equipped = ecs
+            .entry_ref(item)
+            .is_ok_and(|entry| entry.get_component::<Equipped>().is_ok());
+        //同一栏位只能装备一件物品，先卸下该栏位上的其它装备
+        <(Entity, &Carried, &Equippable)>::query()
+            .filter(component::<Equipped>())
+            .iter(ecs)
+            .filter(|(_, carried, equippable)| carried.0 == user && equippable.0 == slot)
+            .for_each(|(other, _, _)| commands.remove_component::<Equipped>(*other));
+        if !already_equipped {
+            commands.add_component(item, Equipped);
+        }
+    }
+
+    for (target, amount) in healing_to_apply {
+        if let Ok(mut target) = ecs.entry_mut(target)
+            && let Ok(health) = target.get_component_mut::<Health>()
+        {
+            health.current = i32::min(health.max, health.current + amount);
+        }
+    }
+
+    if !teleports.is_empty() {
+        //传送到被占着的地块会被移动系统拒绝，卷轴就白用了
+        let floor_tiles: Vec<Point> = map
+            .tiles
+            .iter()
+            .enumerate()
+            .filter(|(_, t)| **t == TileType::Floor)
+            .map(|(idx, _)| map.index_to_point2d(idx))
+            .filter(|pos| index.blocker_at(*pos).is_none())
+            .collect();
+        for entity in teleports {
+            if let Some(destination) = rng.random_slice_entry(&floor_tiles) {
+                commands.push((
+                    (),
+                    WantsToMove {
+                        entity,
+                        destination: *destination,
+                    },
+                ));
+            }
+        }
+    }
+
+    for (user, center, area) in area_damage {
+        let victims: Vec<(Entity, Point)> = <(Entity, &Point)>::query()
+            .filter(component::<Health>())
+            .iter(ecs)
+            .filter(|(entity, pos)| {
+                let offset = **pos - center;
+                **entity != user
+                    && offset.x * offset.x + offset.y * offset.y <= area.radius * area.radius
+            })
+            .map(|(entity, pos)| (*entity, *pos))
+            .collect();
+        for (victim, pos) in victims {
+            if let Ok(mut victim_entry) = ecs.entry_mut(victim) {
+                let is_player = victim_entry.get_component::<Player>().is_ok();
+                if let Ok(health) = victim_entry.get_component_mut::<Health>() {
+                    health.current -= area.damage;
+                    if health.current < 1 && !is_player {
+                        commands.remove(victim);
+                        index.remove(victim, pos);
+                    }
+                }
+            }
+        }
+    }
+}
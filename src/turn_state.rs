@@ -1,8 +1,14 @@
-#[derive(Copy, Clone, Debug, PartialEq)]  
+use serde::{Deserialize, Serialize};
+
+#[derive(Copy, Clone, Debug, PartialEq, Serialize, Deserialize)]  
 pub enum TurnState {  
     AwaitingInput,  
     PlayerTurn,  
     MonsterTurn,
     GameOver,
     Victory,
+    NextLevel,
+    ShowingInventory,
+    MainMenu,
+    ShowingMapGen,
 }
\ No newline at end of file
@@ -0,0 +1,86 @@
+use crate::prelude::*;
+
+/** 每张快照停留的毫秒数 */
+const SNAPSHOT_MS: f32 = 80.0;
+
+/** `--visualize`模式：开始每一层之前，在UI控制台上逐张回放地图生成过程中的快照 */
+pub struct MapGenVisualizer {
+    history: Vec<Map>,
+    theme: Box<dyn MapTheme>,
+    method_seed: i32,
+    seed: u64,
+    index: usize,
+    timer: f32,
+}
+
+impl MapGenVisualizer {
+    /** 没有生成过程的地图（例如文本地图）只显示最终的样子 */
+    pub fn new(mb: &MapBuilder, seed: u64) -> Self {
+        let history = if mb.history.is_empty() {
+            vec![mb.map.clone()]
+        } else {
+            mb.history.clone()
+        };
+        Self {
+            history,
+            theme: mb.theme.kind().theme(),
+            method_seed: mb.method_seed,
+            seed,
+            index: 0,
+            timer: 0.0,
+        }
+    }
+
+    /** 绘制当前快照并按时间前进，返回true表示回放结束，可以开始游戏 */
+    pub fn tick(&mut self, ctx: &mut BTerm) -> bool {
+        ctx.set_active_console(2);
+        self.timer += ctx.frame_time_ms;
+        if self.timer > SNAPSHOT_MS && self.index + 1 < self.history.len() {
+            self.timer = 0.0;
+            self.index += 1;
+        }
+        let (console_width, console_height) = ctx.get_char_size();
+        let console_width = i32::try_from(console_width).unwrap_or(0);
+        let console_height = i32::try_from(console_height).unwrap_or(0);
+        if let Some(map) = self.history.get(self.index) {
+            //地图居中，超出控制台的部分不画
+            let offset = Point::new(
+                ((console_width - map.width) / 2).max(0),
+                ((console_height - map.height) / 2).max(0),
+            );
+            for y in 0..map.height.min(console_height) {
+                for x in 0..map.width.min(console_width) {
+                    let tile = map.tiles[map.idx(x, y)];
+                    ctx.set(
+                        x + offset.x,
+                        y + offset.y,
+                        self.theme.tile_color(tile),
+                        BLACK,
+                        self.theme.tile_to_render(tile),
+                    );
+                }
+            }
+        }
+        ctx.print_color(
+            1,
+            1,
+            YELLOW,
+            BLACK,
+            format!(
+                "Seed: {}  method_seed: {}  snapshot {}/{}",
+                self.seed,
+                self.method_seed,
+                self.index + 1,
+                self.history.len()
+            ),
+        );
+        let finished = self.index + 1 >= self.history.len();
+        let hint = if finished {
+            "Press Space to start the level."
+        } else {
+            "Press Space to skip."
+        };
+        ctx.print_color(1, console_height - 2, GREEN, BLACK, hint);
+        matches!(ctx.key, Some(VirtualKeyCode::Space))
+    }
+}